@@ -2,12 +2,13 @@ use failure::Error;
 use std::cell::RefCell;
 use std::fs::read;
 use std::rc::Rc;
+use std::sync::Arc;
 use wasm_rust_api::*;
 
 fn main() -> Result<(), Error> {
     let wasm = read("gcd.wasm")?;
-    let engine = Rc::new(RefCell::new(Engine::default()));
-    let store = Rc::new(RefCell::new(Store::new(engine)));
+    let engine = Arc::new(Engine::default());
+    let store = Rc::new(RefCell::new(Store::new(engine)?));
     let module = Rc::new(RefCell::new(Module::new(store.clone(), &wasm)?));
     let gcd_index = module
         .borrow()
@@ -0,0 +1,73 @@
+//! Calls functions with multiple results in both directions: the wasm
+//! export `round_trip` calls the host callable `swap`, and returns its
+//! results rearranged.
+
+use failure::{bail, Error};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+use wasm_rust_api::*;
+
+const WAT: &str = r#"
+(module
+  (func $swap (import "" "swap") (param i32 f64) (result f64 i32))
+  (func (export "round_trip") (param i32 f64 i64 f32) (result i64 f64 i32 f32)
+    (local $a i32)
+    (local $b f64)
+    local.get 0
+    local.get 1
+    call $swap
+    local.set $a
+    local.set $b
+    local.get 2
+    local.get $b
+    local.get $a
+    local.get 3)
+)
+"#;
+
+struct Swap;
+
+impl Callable for Swap {
+    fn call(&self, params: &[Val], results: &mut [Val]) -> Result<(), Rc<RefCell<Trap>>> {
+        results[0] = params[1].clone();
+        results[1] = params[0].clone();
+        Ok(())
+    }
+}
+
+fn main() -> Result<(), Error> {
+    let mut config = Config::default();
+    config.set_wasm_multi_value(true);
+    let engine = Arc::new(Engine::new(config));
+    let store = Rc::new(RefCell::new(Store::new(engine)?));
+    let module = Rc::new(RefCell::new(Module::new(store.clone(), WAT.as_bytes())?));
+
+    let swap_type = FuncType::new(
+        vec![ValType::I32, ValType::F64].into_boxed_slice(),
+        vec![ValType::F64, ValType::I32].into_boxed_slice(),
+    );
+    let swap = Rc::new(RefCell::new(Func::new(
+        store.clone(),
+        swap_type,
+        Rc::new(Swap),
+    )));
+
+    let imports = vec![Rc::new(RefCell::new(Extern::Func(swap)))];
+    let instance = Instance::new(store.clone(), module, &imports)?;
+    let round_trip = instance.exports()[0].borrow().func().clone();
+    let results = round_trip.borrow().call(&[
+        Val::from(1i32),
+        Val::from(2.5f64),
+        Val::from(3i64),
+        Val::from(4.5f32),
+    ])?;
+
+    println!("round_trip: {:?}", results);
+    match *results {
+        [Val::I64(3), Val::F64(b), Val::I32(1), Val::F32(d)]
+            if f64::from_bits(b) == 2.5 && f32::from_bits(d) == 4.5 => {}
+        _ => bail!("unexpected results {:?}", results),
+    }
+    Ok(())
+}
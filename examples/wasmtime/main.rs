@@ -46,9 +46,9 @@ use std::path::Component;
 use std::path::{Path, PathBuf};
 use std::process::exit;
 use std::rc::Rc;
+use std::sync::Arc;
 use wabt;
 use wasi_common::preopen_dir;
-use wasmtime_environ::cache_conf;
 use wasmtime_jit::Features;
 use wasmtime_wasi::instantiate_wasi;
 use wasmtime_wast::instantiate_spectest;
@@ -56,7 +56,7 @@ use wasmtime_wast::instantiate_spectest;
 #[cfg(feature = "wasi-c")]
 use wasmtime_wasi_c::instantiate_wasi_c;
 
-use wasm_rust_api::{Config, Engine, Instance, Module, Store};
+use wasm_rust_api::{CacheConfig, Config, Engine, Instance, Module, Store};
 
 mod utils;
 
@@ -77,7 +77,7 @@ Usage:
 Options:
     --invoke=<fn>       name of function to run
     -o, --optimize      runs optimization passes on the translated functions
-    -c, --cache         enable caching system
+    -c, --cache         enable caching system, in the user's cache directory
     --cache-dir=<cache_dir>
                         enable caching system, use specified cache directory
     -g                  generate debug information
@@ -130,6 +130,17 @@ fn read_wasm(path: PathBuf) -> Result<Vec<u8>, String> {
     })
 }
 
+/// The cache directory of the current user. Cache entries are loaded as
+/// executable code, so a directory that other users can write to, like the
+/// shared temporary directory, must not be used.
+fn default_cache_dir() -> Option<PathBuf> {
+    let cache_home = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))?;
+    Some(cache_home.join("wasm-rust-api"))
+}
+
 fn compute_preopen_dirs(flag_dir: &[String], flag_mapdir: &[String]) -> Vec<(String, File)> {
     let mut preopen_dirs = Vec::new();
 
@@ -217,11 +228,6 @@ fn main() {
         utils::init_file_per_thread_logger();
     }
 
-    cache_conf::init(
-        args.flag_cache || args.flag_cache_dir.is_some(),
-        args.flag_cache_dir.as_ref(),
-    );
-
     let mut flag_builder = settings::builder();
     let mut features: Features = Default::default();
 
@@ -244,9 +250,24 @@ fn main() {
         flag_builder.set("opt_level", "best").unwrap();
     }
 
-    let config = Config::new(settings::Flags::new(flag_builder), features, debug_info);
-    let engine = Rc::new(RefCell::new(Engine::new(config)));
-    let store = Rc::new(RefCell::new(Store::new(engine)));
+    let mut config = Config::new(flag_builder, features, debug_info);
+
+    // Enable the compilation cache if requested.
+    if args.flag_cache || args.flag_cache_dir.is_some() {
+        let cache_dir = args
+            .flag_cache_dir
+            .as_ref()
+            .map(PathBuf::from)
+            .or_else(default_cache_dir)
+            .unwrap_or_else(|| {
+                println!("no per-user cache directory found, use --cache-dir");
+                exit(1);
+            });
+        config.set_cache_config(CacheConfig::new(cache_dir));
+    }
+
+    let engine = Arc::new(Engine::new(config));
+    let store = Rc::new(RefCell::new(Store::new(engine).expect("creating store")));
 
     let mut module_registry = HashMap::new();
 
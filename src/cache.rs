@@ -0,0 +1,157 @@
+//! On-disk cache of compiled modules. Entries are serialized modules, keyed
+//! by a hash of the module binary, the code generator configuration and the
+//! crate version.
+
+use crate::context::Context;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Once;
+
+use wasmtime_environ::cache_conf;
+
+const DEFAULT_SIZE_LIMIT: u64 = 512 * 1024 * 1024;
+
+/// Configuration of the compilation cache of an `Engine`.
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    enabled: bool,
+    directory: PathBuf,
+    size_limit: u64,
+}
+
+impl CacheConfig {
+    /// Caches compiled modules in `directory`, which is created when needed.
+    /// Entries are loaded as executable code without further checks, so only
+    /// trusted users may be able to write to the directory.
+    pub fn new(directory: PathBuf) -> CacheConfig {
+        CacheConfig {
+            enabled: true,
+            directory,
+            size_limit: DEFAULT_SIZE_LIMIT,
+        }
+    }
+
+    pub fn disabled() -> CacheConfig {
+        CacheConfig {
+            enabled: false,
+            directory: PathBuf::new(),
+            size_limit: DEFAULT_SIZE_LIMIT,
+        }
+    }
+
+    /// Sets the total size, in bytes, the cache directory may reach before
+    /// the least recently written entries are removed.
+    pub fn set_size_limit(&mut self, size_limit: u64) {
+        self.size_limit = size_limit;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    pub fn size_limit(&self) -> u64 {
+        self.size_limit
+    }
+}
+
+/// The cache entry for a module binary compiled in a given context.
+pub(crate) struct ModuleCacheEntry<'config> {
+    config: &'config CacheConfig,
+    path: Option<PathBuf>,
+}
+
+impl<'config> ModuleCacheEntry<'config> {
+    /// The entry is disabled with debug info, which is not serialized.
+    pub fn new(config: &'config CacheConfig, context: &Context, binary: &[u8]) -> Self {
+        let path = if config.enabled && !context.debug_info() {
+            Some(config.directory.join(cache_key(context, binary)))
+        } else {
+            None
+        };
+        ModuleCacheEntry { config, path }
+    }
+
+    pub fn get(&self) -> Option<Vec<u8>> {
+        self.path.as_ref().and_then(|path| fs::read(path).ok())
+    }
+
+    /// Stores the module serialized by `serialize`. The cache is best
+    /// effort: I/O errors are ignored.
+    pub fn update<F: FnOnce() -> Vec<u8>>(&self, serialize: F) {
+        if let Some(ref path) = self.path {
+            let _ = write_entry(path, &serialize())
+                .and_then(|()| evict(&self.config.directory, self.config.size_limit));
+        }
+    }
+}
+
+fn cache_key(context: &Context, binary: &[u8]) -> String {
+    let isa = context.isa();
+    let mut hasher = Sha256::new();
+    hasher.input(env!("CARGO_PKG_VERSION").as_bytes());
+    hasher.input(isa.triple().to_string().as_bytes());
+    hasher.input(isa.to_string().as_bytes());
+    hasher.input(binary);
+    hasher
+        .result()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn write_entry(path: &Path, serialized: &[u8]) -> io::Result<()> {
+    if let Some(directory) = path.parent() {
+        fs::create_dir_all(directory)?;
+    }
+    // Write to a temporary file first, so concurrent readers (possibly in
+    // other processes) never observe a partially written entry. The name is
+    // unique to the writer, as threads may compile the same module at once.
+    static NEXT_TEMP_ID: AtomicUsize = AtomicUsize::new(0);
+    let temp_id = NEXT_TEMP_ID.fetch_add(1, Ordering::Relaxed);
+    let temp_path = path.with_extension(format!("{}-{}.tmp", std::process::id(), temp_id));
+    fs::write(&temp_path, serialized)?;
+    fs::rename(&temp_path, path)
+}
+
+/// Removes the oldest entries until the directory fits in `size_limit`.
+fn evict(directory: &Path, size_limit: u64) -> io::Result<()> {
+    let mut entries = fs::read_dir(directory)?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let metadata = entry.metadata().ok()?;
+            if !metadata.is_file() {
+                return None;
+            }
+            Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+        })
+        .collect::<Vec<_>>();
+    let mut total_size: u64 = entries.iter().map(|(_, len, _)| len).sum();
+    entries.sort();
+    for (_, len, path) in entries {
+        if total_size <= size_limit {
+            break;
+        }
+        if fs::remove_file(&path).is_ok() {
+            total_size -= len;
+        }
+    }
+    Ok(())
+}
+
+/// The global cache of wasmtime-environ is superseded by the `Engine` one,
+/// but it still has to be initialized (disabled) once before compiling.
+pub(crate) fn init_environ_cache() {
+    static INIT: Once = Once::new();
+    INIT.call_once(|| cache_conf::init(false, None::<&str>));
+}
@@ -1,5 +1,6 @@
 use crate::runtime::Store;
 use crate::trap::Trap;
+use crate::types::FuncType;
 use crate::values::Val;
 use core::any::Any;
 use std::cell::RefCell;
@@ -14,6 +15,7 @@ pub trait Callable: Any {
 
 pub(crate) struct WasmtimeFn {
     store: Rc<RefCell<Store>>,
+    r#type: FuncType,
     signature: ir::Signature,
     body: *const VMFunctionBody,
     vmctx: *mut VMContext,
@@ -22,12 +24,14 @@ pub(crate) struct WasmtimeFn {
 impl WasmtimeFn {
     pub fn new(
         store: Rc<RefCell<Store>>,
+        r#type: FuncType,
         signature: ir::Signature,
         body: *const VMFunctionBody,
         vmctx: *mut VMContext,
     ) -> WasmtimeFn {
         WasmtimeFn {
             store,
+            r#type,
             signature,
             body,
             vmctx,
@@ -37,26 +41,22 @@ impl WasmtimeFn {
 impl Callable for WasmtimeFn {
     fn call(&self, params: &[Val], results: &mut [Val]) -> Result<(), Rc<RefCell<Trap>>> {
         use core::cmp::max;
-        use core::{mem, ptr};
+        use core::mem;
 
-        let mut store = self.store.borrow_mut();
-
-        let context = store.context();
-        let value_size = mem::size_of::<u64>();
-        let mut values_vec: Vec<u64> = vec![0; max(params.len(), results.len())];
+        // The store must not stay borrowed during the call: host functions
+        // called from wasm use it, e.g. to access globals or tables.
+        let (refs, mut context) = {
+            let mut store = self.store.borrow_mut();
+            (store.refs().clone(), store.context().clone())
+        };
+        let value_size = mem::size_of::<u128>();
+        let mut values_vec: Vec<u128> = vec![0; max(params.len(), results.len())];
 
         // Store the argument values into `values_vec`.
         for (index, arg) in params.iter().enumerate() {
             unsafe {
                 let ptr = values_vec.as_mut_ptr().add(index);
-
-                match arg {
-                    Val::I32(x) => ptr::write(ptr as *mut i32, *x),
-                    Val::I64(x) => ptr::write(ptr as *mut i64, *x),
-                    Val::F32(x) => ptr::write(ptr as *mut u32, *x),
-                    Val::F64(x) => ptr::write(ptr as *mut u64, *x),
-                    _ => unimplemented!("WasmtimeFn arg"),
-                }
+                arg.write_value_to(ptr, &mut refs.borrow_mut());
             }
         }
 
@@ -77,17 +77,19 @@ impl Callable for WasmtimeFn {
             return Err(Rc::new(RefCell::new(Trap::new(message))));
         }
 
-        // Load the return values out of `values_vec`.
-        for (index, abi_param) in self.signature.returns.iter().enumerate() {
-            unsafe {
+        // Load the return values out of `values_vec`. The signature has the
+        // same cranelift type for both kinds of references, so the results
+        // are read with the types of the function.
+        for (index, ty) in self.r#type.results().iter().enumerate() {
+            let val = unsafe {
                 let ptr = values_vec.as_ptr().add(index);
-
-                results[index] = match abi_param.value_type {
-                    ir::types::I32 => Val::I32(ptr::read(ptr as *const i32)),
-                    ir::types::I64 => Val::I64(ptr::read(ptr as *const i64)),
-                    ir::types::F32 => Val::F32(ptr::read(ptr as *const u32)),
-                    ir::types::F64 => Val::F64(ptr::read(ptr as *const u64)),
-                    other => panic!("unsupported value type {:?}", other),
+                Val::read_value_from(ptr, ty, &refs.borrow())
+            };
+            match val {
+                Some(val) => results[index] = val,
+                None => {
+                    let message = format!("result {} is not a reference of type {:?}", index, ty);
+                    return Err(Rc::new(RefCell::new(Trap::new(message))));
                 }
             }
         }
@@ -0,0 +1,523 @@
+//! Ahead-of-time compilation of a module's functions. The machine code is
+//! produced once, when a `Module` is created, and is copied into a store's
+//! code memory the first time the module is instantiated in it. Every
+//! instantiation links the code against its own imports.
+
+use crate::cache::init_environ_cache;
+use crate::context::{Context, PlacedModule};
+use crate::runtime::host_isa_builder;
+use crate::trap::Trap;
+use failure::Error;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::rc::Rc;
+use std::sync::Arc;
+
+use cranelift_codegen::{ir, isa};
+use cranelift_entity::{EntityRef, PrimaryMap};
+use cranelift_wasm::{DefinedFuncIndex, DefinedMemoryIndex};
+use serde::{Deserialize, Serialize};
+use target_lexicon::{Architecture, OperatingSystem, Triple, HOST};
+use wasmtime_debug::{emit_debugsections_image, read_debuginfo};
+use wasmtime_environ::cranelift::Cranelift;
+use wasmtime_environ::{
+    Compilation, Compiler as _, Module, ModuleAddressMap, ModuleEnvironment, ModuleTranslation,
+    ModuleVmctxInfo, Relocations, StackSlots, VMOffsets, ValueLabelsRanges,
+};
+use wasmtime_jit::{link_module, target_tunables, Resolver};
+use wasmtime_runtime::{
+    GdbJitImageRegistration, InstanceHandle, InstantiationError, VMFunctionBody,
+};
+
+fn translate<'data>(
+    isa: &dyn isa::TargetIsa,
+    binary: &'data [u8],
+) -> Result<ModuleTranslation<'data>, Error> {
+    let tunables = target_tunables(isa.triple());
+    let translation = ModuleEnvironment::new(isa.frontend_config(), tunables).translate(binary)?;
+    Ok(translation)
+}
+
+/// The number of integer and floating-point (or vector) registers results are
+/// returned in on the `triple`'s architecture. The code generator does not
+/// return results in memory, so a function can have no more results than
+/// these.
+fn return_registers(triple: &Triple) -> (usize, usize) {
+    match triple.architecture {
+        // rax and xmm0 under the Windows fastcall convention.
+        Architecture::X86_64 if triple.operating_system == OperatingSystem::Windows => (1, 1),
+        // rax and rdx, xmm0 and xmm1.
+        Architecture::X86_64 | Architecture::I386 | Architecture::I586 | Architecture::I686 => {
+            (2, 2)
+        }
+        // a0 and a1, fa0 and fa1.
+        Architecture::Riscv32
+        | Architecture::Riscv32imac
+        | Architecture::Riscv32imc
+        | Architecture::Riscv64 => (2, 2),
+        _ => (1, 1),
+    }
+}
+
+/// Checks that the results of a function of the `signature` fit in the
+/// return registers of the `isa`.
+pub(crate) fn check_returns(
+    isa: &dyn isa::TargetIsa,
+    signature: &ir::Signature,
+) -> Result<(), Error> {
+    let (max_ints, max_floats) = return_registers(isa.triple());
+    let mut ints = 0;
+    let mut floats = 0;
+    for r in &signature.returns {
+        if r.value_type.is_float() || r.value_type.is_vector() {
+            floats += 1;
+        } else if r.value_type.bits() > u16::from(isa.pointer_bits()) {
+            // Split into a register pair, e.g. an i64 on a 32-bit target.
+            ints += 2;
+        } else {
+            ints += 1;
+        }
+    }
+    if ints > max_ints || floats > max_floats {
+        failure::bail!(
+            "functions can return at most {} integer and {} floating-point values on {}, not {} and {}",
+            max_ints,
+            max_floats,
+            isa.triple(),
+            ints,
+            floats
+        );
+    }
+    Ok(())
+}
+
+/// Identifies the code generator configuration the machine code was produced
+/// with. Serialized artifacts are only loaded by a matching configuration.
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct CodeTarget {
+    version: String,
+    triple: String,
+    flags: String,
+}
+
+impl CodeTarget {
+    fn new(isa: &dyn isa::TargetIsa) -> CodeTarget {
+        CodeTarget {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            triple: isa.triple().to_string(),
+            // Includes both the shared and the ISA specific flags.
+            flags: isa.to_string(),
+        }
+    }
+}
+
+/// Returns the boolean settings enabled in `isa`, as listed by its `Display`
+/// implementation (one "name = value" per line).
+fn enabled_settings(isa: &dyn isa::TargetIsa) -> HashSet<String> {
+    isa.to_string()
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.split('=').map(str::trim);
+            match (parts.next(), parts.next()) {
+                (Some(name), Some("true")) => Some(name.to_string()),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// What a debugger needs to map the compiled code back to the module. The
+/// debug image refers to code addresses, so it is emitted on every
+/// instantiation.
+struct DebugArtifacts {
+    address_transform: ModuleAddressMap,
+    value_ranges: ValueLabelsRanges,
+    stack_slots: StackSlots,
+}
+
+/// The compiled functions of a module, not yet placed in executable memory.
+pub(crate) struct CompiledModule {
+    target: CodeTarget,
+    compilation: Compilation,
+    relocations: Relocations,
+    // Not serialized: modules are compiled again when debug info is enabled.
+    debug: Option<DebugArtifacts>,
+}
+
+impl CompiledModule {
+    pub fn new(context: &Context, binary: &[u8]) -> Result<CompiledModule, Error> {
+        let isa = context.isa();
+        let translation = translate(isa, binary)?;
+        for signature in translation.module.signatures.values() {
+            check_returns(isa, signature)?;
+        }
+        init_environ_cache();
+        let (compilation, relocations, address_transform, value_ranges, stack_slots) =
+            Cranelift::compile_module(
+                &translation.module,
+                translation.function_body_inputs,
+                isa,
+                context.debug_info(),
+            )?;
+        let debug = if context.debug_info() {
+            Some(DebugArtifacts {
+                address_transform,
+                value_ranges,
+                stack_slots,
+            })
+        } else {
+            None
+        };
+        Ok(CompiledModule {
+            target: CodeTarget::new(isa),
+            compilation,
+            relocations,
+            debug,
+        })
+    }
+
+    /// Writes the `binary` and its compiled code. The code target goes first,
+    /// so that artifacts with a different layout can still be rejected.
+    pub fn serialize(&self, binary: &[u8]) -> Vec<u8> {
+        let mut serialized = bincode::serialize(&self.target).expect("serialize target");
+        bincode::serialize_into(
+            &mut serialized,
+            &(binary, &self.compilation, &self.relocations),
+        )
+        .expect("serialize compiled module");
+        serialized
+    }
+
+    /// Reads an artifact produced by `serialize`, returning the compiled code
+    /// and the module's binary.
+    ///
+    /// # Safety
+    ///
+    /// The machine code of the artifact runs as is when the module is
+    /// instantiated; only its header is checked. The artifact must come from
+    /// a trusted source.
+    pub unsafe fn deserialize(
+        context: &Context,
+        serialized: &[u8],
+    ) -> Result<(CompiledModule, Vec<u8>), Error> {
+        if context.debug_info() {
+            failure::bail!("serialized modules cannot be loaded with debug info enabled");
+        }
+        // Bounding the reads by the artifact's size keeps a corrupted length
+        // from causing a huge allocation.
+        let mut config = bincode::config();
+        config.limit(serialized.len() as u64);
+        let mut reader = serialized;
+        let target: CodeTarget = config.deserialize_from(&mut reader)?;
+        let expected = CodeTarget::new(context.isa());
+        if target.version != expected.version {
+            failure::bail!(
+                "module was serialized by version {} (expected {})",
+                target.version,
+                expected.version
+            );
+        }
+        if target.triple != expected.triple {
+            failure::bail!(
+                "module was compiled for {} (expected {})",
+                target.triple,
+                expected.triple
+            );
+        }
+        if target != expected {
+            failure::bail!("module was compiled with incompatible code generator flags");
+        }
+        let (binary, compilation, relocations) = config.deserialize_from(&mut reader)?;
+        Ok((
+            CompiledModule {
+                target,
+                compilation,
+                relocations,
+                debug: None,
+            },
+            binary,
+        ))
+    }
+
+    /// Instantiates the module in the `context`. The functions are placed in
+    /// the context's code memory the first time, and shared by the instances
+    /// that follow.
+    pub fn instantiate(
+        self: &Arc<Self>,
+        binary: &[u8],
+        context: &mut Context,
+        resolver: &mut dyn Resolver,
+        global_exports: Rc<RefCell<HashMap<String, Option<wasmtime_runtime::Export>>>>,
+    ) -> Result<InstanceHandle, Error> {
+        if *context.isa().triple() != HOST {
+            failure::bail!(
+                "cannot instantiate code compiled for {} on {}",
+                context.isa().triple(),
+                HOST
+            );
+        }
+        let host_isa = host_isa_builder()?.finish(context.isa().flags().clone());
+        let host_settings = enabled_settings(&*host_isa);
+        if let Some(setting) = enabled_settings(context.isa())
+            .difference(&host_settings)
+            .next()
+        {
+            failure::bail!(
+                "cannot instantiate code compiled with {}, which the host does not support",
+                setting
+            );
+        }
+
+        // Translating again is cheap compared to compiling, and gives us the
+        // environment module and the data initializers borrowed from `binary`.
+        let ModuleTranslation {
+            module,
+            data_initializers,
+            ..
+        } = translate(context.isa(), binary)?;
+
+        let defined_functions = module.functions.len() - module.imported_funcs;
+        if self.compilation.len() != defined_functions
+            || self.relocations.len() != defined_functions
+        {
+            failure::bail!(
+                "compiled code has {} functions, but the module defines {}",
+                self.compilation.len(),
+                defined_functions
+            );
+        }
+        // The runtime only has room for 64 bits in the globals it defines;
+        // imported ones are accessed through a pointer, see `create_global`.
+        let defines_v128_global = module
+            .globals
+            .values()
+            .skip(module.imported_globals)
+            .any(|global| global.ty == ir::types::I8X16);
+        if defines_v128_global {
+            failure::bail!("modules defining globals of type v128 are not supported");
+        }
+
+        let key = &**self as *const CompiledModule;
+        let placed = context
+            .placed_modules()
+            .get(&key)
+            .map(|placed| placed.functions.clone());
+        let (allocated_functions, imports) = match placed {
+            // The code is already relocated; only the imports are resolved.
+            Some(functions) => {
+                let imports = link_module(
+                    &module,
+                    &functions,
+                    &self.compilation.get_jt_offsets(),
+                    Relocations::new(),
+                    resolver,
+                )?;
+                (functions, imports)
+            }
+            None => {
+                let mut functions: PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]> =
+                    PrimaryMap::with_capacity(self.compilation.len());
+                {
+                    let mut code_memory = context.code_memory();
+                    for function in self.compilation.into_iter() {
+                        let body = code_memory
+                            .allocate_copy_of_byte_slice(&function.body)
+                            .map_err(failure::err_msg)?;
+                        functions.push(body as *mut [VMFunctionBody]);
+                    }
+                }
+                let imports = link_module(
+                    &module,
+                    &functions,
+                    &self.compilation.get_jt_offsets(),
+                    self.relocations.clone(),
+                    resolver,
+                )?;
+                context.code_memory().publish();
+                context.placed_modules().insert(
+                    key,
+                    PlacedModule {
+                        _module: self.clone(),
+                        functions: functions.clone(),
+                    },
+                );
+                (functions, imports)
+            }
+        };
+
+        let signatures = {
+            let mut compiler = context.compiler();
+            let signature_registry = compiler.signatures();
+            module
+                .signatures
+                .values()
+                .map(|sig| signature_registry.register(sig))
+                .collect::<PrimaryMap<_, _>>()
+        };
+
+        let finished_functions = allocated_functions
+            .values()
+            .map(|allocated| {
+                let fatptr: *const [VMFunctionBody] = *allocated;
+                fatptr as *const VMFunctionBody
+            })
+            .collect::<PrimaryMap<DefinedFuncIndex, _>>();
+
+        let dbg_jit_registration = match self.debug {
+            Some(ref debug) => {
+                let image = self.emit_debug_image(
+                    context.isa(),
+                    binary,
+                    &module,
+                    &allocated_functions,
+                    debug,
+                )?;
+                Some(Rc::new(GdbJitImageRegistration::register(image)))
+            }
+            None => None,
+        };
+
+        let instance = InstanceHandle::new(
+            Rc::new(module),
+            global_exports,
+            finished_functions.into_boxed_slice(),
+            imports,
+            &data_initializers,
+            signatures.into_boxed_slice(),
+            dbg_jit_registration,
+            Box::new(()),
+        )
+        .map_err(|e| match e {
+            InstantiationError::StartTrap(message) => Error::from(Trap::new(message)),
+            e => Error::from(e),
+        })?;
+        Ok(instance)
+    }
+
+    /// Builds the object file that describes the functions, as placed at
+    /// `allocated_functions`, to the GDB JIT interface.
+    fn emit_debug_image(
+        &self,
+        isa: &dyn isa::TargetIsa,
+        binary: &[u8],
+        module: &Module,
+        allocated_functions: &PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
+        debug: &DebugArtifacts,
+    ) -> Result<Vec<u8>, Error> {
+        let target_config = isa.frontend_config();
+        let funcs = allocated_functions
+            .iter()
+            .map(|(i, allocated)| (*allocated as *const u8, self.compilation.get(i).body.len()))
+            .collect::<Vec<_>>();
+        let module_vmctx_info = {
+            let ofs = VMOffsets::new(target_config.pointer_bytes(), module);
+            ModuleVmctxInfo {
+                memory_offset: ofs.vmctx_vmmemory_definition_base(DefinedMemoryIndex::new(0))
+                    as i64,
+                stack_slots: debug.stack_slots.clone(),
+            }
+        };
+        emit_debugsections_image(
+            isa.triple().clone(),
+            &target_config,
+            &read_debuginfo(binary),
+            &module_vmctx_info,
+            &debug.address_transform,
+            &debug.value_ranges,
+            &funcs,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Config;
+
+    const EMPTY_MODULE: &[u8] = b"\0asm\x01\0\0\0";
+
+    fn serialized_module(context: &Context) -> Vec<u8> {
+        let compiled = CompiledModule::new(context, EMPTY_MODULE).unwrap();
+        compiled.serialize(EMPTY_MODULE)
+    }
+
+    fn tamper(serialized: &[u8], f: impl FnOnce(&mut CodeTarget)) -> Vec<u8> {
+        let mut reader = serialized;
+        let mut target: CodeTarget = bincode::deserialize_from(&mut reader).unwrap();
+        f(&mut target);
+        let mut tampered = bincode::serialize(&target).unwrap();
+        tampered.extend_from_slice(reader);
+        tampered
+    }
+
+    #[test]
+    fn round_trip() {
+        let context = Context::create(&Config::default()).unwrap();
+        let serialized = serialized_module(&context);
+        let (_, binary) = unsafe { CompiledModule::deserialize(&context, &serialized) }.unwrap();
+        assert_eq!(binary, EMPTY_MODULE);
+    }
+
+    #[test]
+    fn rejects_other_code_targets() {
+        let context = Context::create(&Config::default()).unwrap();
+        let serialized = serialized_module(&context);
+        let tampered = [
+            tamper(&serialized, |target| target.version.push_str("-other")),
+            tamper(&serialized, |target| {
+                target.triple = "riscv64-unknown-none".into()
+            }),
+            tamper(&serialized, |target| target.flags.push_str("other")),
+        ];
+        for serialized in tampered.iter() {
+            assert!(unsafe { CompiledModule::deserialize(&context, serialized) }.is_err());
+        }
+    }
+
+    #[test]
+    fn rejects_corrupted_lengths() {
+        let context = Context::create(&Config::default()).unwrap();
+        let mut serialized = u64::max_value().to_le_bytes().to_vec();
+        serialized.extend_from_slice(&serialized_module(&context));
+        assert!(unsafe { CompiledModule::deserialize(&context, &serialized) }.is_err());
+
+        let serialized = serialized_module(&context);
+        let truncated = &serialized[..serialized.len() - 1];
+        assert!(unsafe { CompiledModule::deserialize(&context, truncated) }.is_err());
+    }
+
+    // (module (func (export "f")))
+    const EXPORT_FUNC: &[u8] = &[
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+        0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // type section
+        0x03, 0x02, 0x01, 0x00, // function section
+        0x07, 0x05, 0x01, 0x01, 0x66, 0x00, 0x00, // export section
+        0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b, // code section
+    ];
+
+    struct NoImports;
+
+    impl Resolver for NoImports {
+        fn resolve(&mut self, _module: &str, _field: &str) -> Option<wasmtime_runtime::Export> {
+            None
+        }
+    }
+
+    #[test]
+    fn places_code_once_per_context() {
+        let mut context = Context::create(&Config::default()).unwrap();
+        let compiled = Arc::new(CompiledModule::new(&context, EXPORT_FUNC).unwrap());
+        let mut address = || {
+            let exports = Rc::new(RefCell::new(HashMap::new()));
+            let mut instance = compiled
+                .instantiate(EXPORT_FUNC, &mut context, &mut NoImports, exports)
+                .unwrap();
+            match instance.lookup("f") {
+                Some(wasmtime_runtime::Export::Function { address, .. }) => address,
+                _ => panic!("missing export"),
+            }
+        };
+        assert_eq!(address(), address());
+        assert_eq!(context.placed_modules().len(), 1);
+    }
+}
@@ -1,29 +1,69 @@
 use std::cell::{RefCell, RefMut};
+use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 use std::rc::Rc;
+use std::sync::Arc;
 
+use cranelift_entity::PrimaryMap;
+use cranelift_wasm::DefinedFuncIndex;
 use wasmtime_jit::{Compiler, Features};
+use wasmtime_runtime::VMFunctionBody;
 
-use cranelift_codegen::settings;
+use cranelift_codegen::{ir, isa};
+use failure::Error;
+
+use crate::compiled::CompiledModule;
+use crate::runtime::Config;
+use crate::trampoline::CodeMemory;
+
+/// The functions of a module, copied into the code memory and relocated the
+/// first time the module is instantiated in a context.
+pub(crate) struct PlacedModule {
+    // Keeps the key of the entry from being reused by another module.
+    pub _module: Arc<CompiledModule>,
+    pub functions: PrimaryMap<DefinedFuncIndex, *mut [VMFunctionBody]>,
+}
 
 #[derive(Clone)]
 pub struct Context {
     compiler: Rc<RefCell<Compiler>>,
+    isa: Rc<dyn isa::TargetIsa>,
+    code_memory: Rc<RefCell<CodeMemory>>,
+    host_trampolines: Rc<RefCell<HashMap<ir::Signature, *const VMFunctionBody>>>,
+    placed_modules: Rc<RefCell<HashMap<*const CompiledModule, PlacedModule>>>,
     features: Features,
     debug_info: bool,
 }
 
 impl Context {
-    pub fn new(compiler: Compiler, features: Features, debug_info: bool) -> Context {
+    pub fn new(
+        compiler: Compiler,
+        isa: Box<dyn isa::TargetIsa>,
+        features: Features,
+        debug_info: bool,
+    ) -> Context {
         Context {
             compiler: Rc::new(RefCell::new(compiler)),
+            isa: isa.into(),
+            code_memory: Rc::new(RefCell::new(CodeMemory::new())),
+            host_trampolines: Rc::new(RefCell::new(HashMap::new())),
+            placed_modules: Rc::new(RefCell::new(HashMap::new())),
             features,
             debug_info,
         }
     }
 
-    pub fn create(flags: settings::Flags, features: Features, debug_info: bool) -> Context {
-        Context::new(create_compiler(flags), features, debug_info)
+    pub fn create(config: &Config) -> Result<Context, Error> {
+        Ok(Context::new(
+            Compiler::new(create_isa(config)?),
+            create_isa(config)?,
+            config.features().clone(),
+            config.debug_info(),
+        ))
+    }
+
+    pub(crate) fn features(&self) -> &Features {
+        &self.features
     }
 
     pub(crate) fn debug_info(&self) -> bool {
@@ -33,6 +73,22 @@ impl Context {
     pub(crate) fn compiler(&mut self) -> RefMut<Compiler> {
         self.compiler.borrow_mut()
     }
+
+    pub(crate) fn isa(&self) -> &dyn isa::TargetIsa {
+        self.isa.as_ref()
+    }
+
+    pub(crate) fn code_memory(&self) -> RefMut<CodeMemory> {
+        self.code_memory.borrow_mut()
+    }
+
+    pub(crate) fn host_trampolines(&self) -> RefMut<HashMap<ir::Signature, *const VMFunctionBody>> {
+        self.host_trampolines.borrow_mut()
+    }
+
+    pub(crate) fn placed_modules(&self) -> RefMut<HashMap<*const CompiledModule, PlacedModule>> {
+        self.placed_modules.borrow_mut()
+    }
 }
 
 impl Hash for Context {
@@ -52,12 +108,6 @@ impl PartialEq for Context {
     }
 }
 
-pub(crate) fn create_compiler(flags: settings::Flags) -> Compiler {
-    let isa = {
-        let isa_builder =
-            cranelift_native::builder().expect("host machine is not a supported target");
-        isa_builder.finish(flags)
-    };
-
-    Compiler::new(isa)
+pub(crate) fn create_isa(config: &Config) -> Result<Box<dyn isa::TargetIsa>, Error> {
+    Ok(config.isa_builder()?.finish(config.flags()))
 }
@@ -1,13 +1,21 @@
 use crate::callable::{Callable, WasmtimeFn};
 use crate::runtime::Store;
-use crate::trap::Trap;
-use crate::types::{ExternType, FuncType, GlobalType, MemoryType};
+use crate::trap::{FrameInfo, Trap};
+use crate::types::{ExternType, FuncType, GlobalType, MemoryType, Mutability, TableType, ValType};
 use crate::values::Val;
+use failure::Error;
 use std::cell::RefCell;
+use std::fmt;
+use std::ptr;
 use std::rc::Rc;
 use std::result::Result;
+use std::sync::Arc;
 
-use crate::trampoline::generate_func_export;
+use crate::trampoline::{
+    generate_func_export, generate_global_export, generate_memory_export,
+    generate_shared_memory_export, generate_table_export, GlobalState, SharedPages,
+};
+use wasmtime_environ::WASM_PAGE_SIZE;
 use wasmtime_runtime::InstanceHandle;
 // Externals
 
@@ -47,20 +55,18 @@ impl Extern {
     pub fn r#type(&self) -> ExternType {
         match self {
             Extern::Func(ft) => ExternType::ExternFunc(ft.borrow().r#type().clone()),
+            Extern::Global(ft) => ExternType::ExternGlobal(ft.borrow().r#type().clone()),
+            Extern::Table(ft) => ExternType::ExternTable(ft.borrow().r#type().clone()),
             Extern::Memory(ft) => ExternType::ExternMemory(ft.borrow().r#type().clone()),
-            _ => unimplemented!("ExternType::type"),
         }
     }
 
     pub(crate) fn get_wasmtime_export(&mut self) -> wasmtime_runtime::Export {
         match self {
-            Extern::Func(f) => {
-                if f.borrow().anchor.is_none() {
-                    generate_func_export(&f).expect("generate_func_export");
-                }
-                f.borrow().anchor.as_ref().unwrap().1.clone()
-            }
-            _ => unimplemented!("get_wasmtime_export"),
+            Extern::Func(f) => Func::wasmtime_export(f),
+            Extern::Global(g) => g.borrow().wasmtime_export().clone(),
+            Extern::Table(t) => t.borrow().wasmtime_export().clone(),
+            Extern::Memory(m) => m.borrow().wasmtime_export().clone(),
         }
     }
 
@@ -69,55 +75,32 @@ impl Extern {
         instance_handle: InstanceHandle,
         export: wasmtime_runtime::Export,
     ) -> Extern {
-        use cranelift_wasm::GlobalInit;
         match export {
-            wasmtime_runtime::Export::Function {
-                address,
-                vmctx,
-                ref signature,
-            } => {
-                let ty = FuncType::from_cranelift_signature(signature.clone());
-                let callable = WasmtimeFn::new(store.clone(), signature.clone(), address, vmctx);
-                let mut f = Func::new(store, ty, Rc::new(callable));
-                f.anchor = Some((instance_handle, export.clone()));
+            wasmtime_runtime::Export::Function { .. } => {
+                let f = Func::from_wasmtime_function(export, store, instance_handle, None);
                 Extern::Func(Rc::new(RefCell::new(f)))
             }
-            wasmtime_runtime::Export::Memory {
-                definition: _,
-                vmctx: _,
-                memory,
-            } => {
-                let ty = MemoryType::from_cranelift_memory(memory.memory.clone());
-                let m = Memory::new(store, ty);
+            wasmtime_runtime::Export::Memory { .. } => {
+                let m = Memory::from_wasmtime_memory(export, store, instance_handle);
                 Extern::Memory(Rc::new(RefCell::new(m)))
             }
-            wasmtime_runtime::Export::Global {
-                definition: _,
-                vmctx: _,
-                global,
-            } => {
-                let ty = GlobalType::from_cranelift_global(global.clone());
-                let val = match global.initializer {
-                    GlobalInit::I32Const(i) => Val::from(i),
-                    GlobalInit::I64Const(i) => Val::from(i),
-                    GlobalInit::F32Const(f) => Val::from_f32_bits(f),
-                    GlobalInit::F64Const(f) => Val::from_f64_bits(f),
-                    _ => unimplemented!("from_wasmtime_export initializer"),
-                };
-                Extern::Global(Rc::new(RefCell::new(Global::new(store, ty, val))))
+            wasmtime_runtime::Export::Global { .. } => {
+                let g = Global::from_wasmtime_global(export, store, instance_handle);
+                Extern::Global(Rc::new(RefCell::new(g)))
             }
             wasmtime_runtime::Export::Table { .. } => {
-                // TODO Extern::Table(Rc::new(RefCell::new(Table::new(store, ty, val))))
-                Extern::Table(Rc::new(RefCell::new(Table)))
+                let t = Table::from_wasmtime_table(export, store, instance_handle);
+                Extern::Table(Rc::new(RefCell::new(t)))
             }
         }
     }
 }
 
 pub struct Func {
-    _store: Rc<RefCell<Store>>,
+    store: Rc<RefCell<Store>>,
     callable: Rc<dyn Callable + 'static>,
     r#type: FuncType,
+    frame_info: Option<FrameInfo>,
     pub(crate) anchor: Option<(InstanceHandle, wasmtime_runtime::Export)>,
 }
 
@@ -128,9 +111,10 @@ impl Func {
         callable: Rc<dyn Callable + 'static>,
     ) -> Func {
         Func {
-            _store: store,
+            store,
             callable,
             r#type,
+            frame_info: None,
             anchor: None,
         }
     }
@@ -139,6 +123,15 @@ impl Func {
         &self.r#type
     }
 
+    pub(crate) fn store(&self) -> &Rc<RefCell<Store>> {
+        &self.store
+    }
+
+    /// Sets the frame added to the trace of the traps raised by this function.
+    pub(crate) fn set_frame_info(&mut self, frame_info: FrameInfo) {
+        self.frame_info = Some(frame_info);
+    }
+
     pub fn param_arity(&self) -> usize {
         self.r#type.params().len()
     }
@@ -151,73 +144,572 @@ impl Func {
         self.callable.as_ref()
     }
 
+    /// Generates the trampoline of a host function, if not done yet. Fails
+    /// if the function's type is not supported for host functions.
+    pub(crate) fn generate_wasmtime_export(func: &Rc<RefCell<Func>>) -> Result<(), Error> {
+        if func.borrow().anchor.is_none() {
+            generate_func_export(func)?;
+        }
+        Ok(())
+    }
+
+    /// The export of the function, generating a trampoline for a host
+    /// function on first use.
+    pub(crate) fn wasmtime_export(func: &Rc<RefCell<Func>>) -> wasmtime_runtime::Export {
+        Func::generate_wasmtime_export(func).expect("generate_func_export");
+        func.borrow().anchor.as_ref().unwrap().1.clone()
+    }
+
+    /// Wraps a function exported by wasm code. `r#type` is its type in the
+    /// module, when known: the signature of the export does not tell the two
+    /// reference types apart, and has `anyref` for both.
+    pub(crate) fn from_wasmtime_function(
+        export: wasmtime_runtime::Export,
+        store: Rc<RefCell<Store>>,
+        instance_handle: InstanceHandle,
+        r#type: Option<FuncType>,
+    ) -> Func {
+        let (address, vmctx, signature) = match export {
+            wasmtime_runtime::Export::Function {
+                address,
+                vmctx,
+                ref signature,
+            } => (address, vmctx, signature.clone()),
+            _ => panic!("wasmtime export is not function"),
+        };
+        let ty = r#type.unwrap_or_else(|| FuncType::from_cranelift_signature(signature.clone()));
+        let callable = WasmtimeFn::new(store.clone(), ty.clone(), signature, address, vmctx);
+        let mut f = Func::new(store, ty, Rc::new(callable));
+        f.anchor = Some((instance_handle, export));
+        f
+    }
+
+    /// Calls the function; traps if `params` does not match its parameter
+    /// types.
     pub fn call(&self, params: &[Val]) -> Result<Box<[Val]>, Rc<RefCell<Trap>>> {
+        self.check_params(params)?;
         let mut results = vec![Val::default(); self.result_arity()];
-        self.callable.call(params, &mut results)?;
+        if let Err(trap) = self.callable.call(params, &mut results) {
+            if let Some(ref frame_info) = self.frame_info {
+                trap.borrow_mut().push_frame(frame_info.clone());
+            }
+            return Err(trap);
+        }
         Ok(results.into_boxed_slice())
     }
+
+    fn check_params(&self, params: &[Val]) -> Result<(), Rc<RefCell<Trap>>> {
+        let types = self.r#type.params();
+        let message = if params.len() != types.len() {
+            format!("expected {} arguments, got {}", types.len(), params.len())
+        } else if let Some(i) = (0..params.len()).find(|&i| !params[i].has_type(&types[i])) {
+            format!(
+                "argument {} is {:?}, not of type {:?}",
+                i, params[i], types[i]
+            )
+        } else {
+            return Ok(());
+        };
+        Err(Rc::new(RefCell::new(Trap::new(message))))
+    }
+}
+
+impl fmt::Debug for Func {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "funcref")
+    }
 }
 
 pub struct Global {
     _store: Rc<RefCell<Store>>,
     r#type: GlobalType,
-    val: Val,
+    wasmtime_export: wasmtime_runtime::Export,
+    #[allow(dead_code)]
+    wasmtime_state: Option<GlobalState>,
+    #[allow(dead_code)]
+    wasmtime_handle: Option<InstanceHandle>,
 }
 
 impl Global {
-    pub fn new(store: Rc<RefCell<Store>>, r#type: GlobalType, val: Val) -> Global {
-        Global {
+    /// Fails if `val` is not of the global's type.
+    pub fn new(store: Rc<RefCell<Store>>, r#type: GlobalType, val: Val) -> Result<Global, Error> {
+        let refs = store.borrow().refs().clone();
+        let (wasmtime_export, wasmtime_state) =
+            generate_global_export(&r#type, val, &mut refs.borrow_mut())?;
+        Ok(Global {
             _store: store,
             r#type,
-            val,
-        }
+            wasmtime_export,
+            wasmtime_state: Some(wasmtime_state),
+            wasmtime_handle: None,
+        })
     }
 
     pub fn r#type(&self) -> &GlobalType {
         &self.r#type
     }
 
-    pub fn get(&self) -> &Val {
-        &self.val
+    fn wasmtime_global_definition(&self) -> *mut wasmtime_runtime::VMGlobalDefinition {
+        match self.wasmtime_export {
+            wasmtime_runtime::Export::Global { definition, .. } => definition,
+            _ => panic!("global definition not found"),
+        }
     }
 
-    pub fn set(&mut self, val: Val) {
-        self.val = val;
+    /// Returns the value of the global. A reference that the store did not
+    /// pass to wasm code with the global's type, or a function that was
+    /// dropped since, reads as null; see `RefTable`.
+    pub fn get(&self) -> Val {
+        let definition = unsafe { &mut *self.wasmtime_global_definition() };
+        unsafe {
+            match self.r#type().content() {
+                ValType::I32 => Val::from(*definition.as_i32()),
+                ValType::I64 => Val::from(*definition.as_i64()),
+                ValType::F32 => Val::from_f32_bits(*definition.as_f32_bits()),
+                ValType::F64 => Val::from_f64_bits(*definition.as_f64_bits()),
+                ValType::AnyRef | ValType::FuncRef => {
+                    let refs = self._store.borrow().refs().clone();
+                    let p = *definition.as_i64() as usize;
+                    let val = refs.borrow().from_raw(p, self.r#type().content());
+                    val.unwrap_or_else(Val::default)
+                }
+                // A `v128` global is one created by `Global::new`, maybe
+                // re-exported, which has room for it; see `create_global`.
+                ValType::V128 => {
+                    Val::V128(ptr::read(self.wasmtime_global_definition() as *const u128))
+                }
+            }
+        }
+    }
+
+    /// Fails if the global is immutable or `val` is not of its type.
+    pub fn set(&mut self, val: Val) -> Result<(), Error> {
+        if let Mutability::Const = self.r#type().mutability() {
+            failure::bail!("immutable global cannot be set");
+        }
+        if !val.has_type(self.r#type().content()) {
+            failure::bail!(
+                "global of type {:?} cannot be set to {:?}",
+                self.r#type().content(),
+                val.r#type()
+            );
+        }
+        let definition = unsafe { &mut *self.wasmtime_global_definition() };
+        unsafe {
+            match val {
+                Val::I32(i) => *definition.as_i32_mut() = i,
+                Val::I64(i) => *definition.as_i64_mut() = i,
+                Val::F32(f) => *definition.as_f32_bits_mut() = f,
+                Val::F64(f) => *definition.as_f64_bits_mut() = f,
+                Val::AnyRef(r) => {
+                    let refs = self._store.borrow().refs().clone();
+                    *definition.as_i64_mut() = refs.borrow_mut().anyref_into_raw(&r) as i64;
+                }
+                Val::FuncRef(f) => {
+                    let refs = self._store.borrow().refs().clone();
+                    *definition.as_i64_mut() = refs.borrow_mut().funcref_into_raw(&f) as i64;
+                }
+                // See `get`.
+                Val::V128(b) => ptr::write(self.wasmtime_global_definition() as *mut u128, b),
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn wasmtime_export(&self) -> &wasmtime_runtime::Export {
+        &self.wasmtime_export
+    }
+
+    pub(crate) fn from_wasmtime_global(
+        export: wasmtime_runtime::Export,
+        store: Rc<RefCell<Store>>,
+        instance_handle: InstanceHandle,
+    ) -> Global {
+        let global = if let wasmtime_runtime::Export::Global { ref global, .. } = export {
+            global
+        } else {
+            panic!("wasmtime export is not global")
+        };
+        let ty = GlobalType::from_cranelift_global(global.clone());
+        Global {
+            _store: store,
+            r#type: ty,
+            wasmtime_export: export,
+            wasmtime_state: None,
+            wasmtime_handle: Some(instance_handle),
+        }
     }
 }
 
-pub struct Table;
+/// Converts `val` into an element of a table of `element`s, or returns `None`
+/// if it is not of that type. The runtime only stores function elements, so
+/// the element of an `anyref` table holds the reference in its `vmctx`.
+fn into_checked_anyfunc(
+    val: Val,
+    element: &ValType,
+    store: &Rc<RefCell<Store>>,
+) -> Option<wasmtime_runtime::VMCallerCheckedAnyfunc> {
+    Some(match (element, val) {
+        (ValType::FuncRef, Val::AnyRef(ref r)) if r.borrow().is_null() => {
+            wasmtime_runtime::VMCallerCheckedAnyfunc::default()
+        }
+        (ValType::FuncRef, Val::FuncRef(f)) => {
+            Func::generate_wasmtime_export(&f).ok()?;
+            let (address, vmctx, signature) = match Func::wasmtime_export(&f) {
+                wasmtime_runtime::Export::Function {
+                    address,
+                    vmctx,
+                    signature,
+                } => (address, vmctx, signature),
+                _ => panic!("expected function export"),
+            };
+            let type_index = store
+                .borrow_mut()
+                .context()
+                .compiler()
+                .signatures()
+                .register(&signature);
+            wasmtime_runtime::VMCallerCheckedAnyfunc {
+                func_ptr: address,
+                type_index,
+                vmctx,
+            }
+        }
+        (ValType::AnyRef, val) => {
+            let refs = store.borrow().refs().clone();
+            let p = match val {
+                Val::AnyRef(r) => refs.borrow_mut().anyref_into_raw(&r),
+                Val::FuncRef(f) => refs.borrow_mut().funcref_into_raw(&f),
+                _ => return None,
+            };
+            wasmtime_runtime::VMCallerCheckedAnyfunc {
+                vmctx: p as *mut wasmtime_runtime::VMContext,
+                ..Default::default()
+            }
+        }
+        _ => return None,
+    })
+}
+
+fn from_checked_anyfunc(
+    item: &wasmtime_runtime::VMCallerCheckedAnyfunc,
+    element: &ValType,
+    store: &Rc<RefCell<Store>>,
+) -> Val {
+    if let ValType::AnyRef = element {
+        let refs = store.borrow().refs().clone();
+        let val = refs.borrow().from_raw(item.vmctx as usize, element);
+        return val.unwrap_or_else(Val::default);
+    }
+    if item.func_ptr.is_null() {
+        return Val::default();
+    }
+    let signature = store
+        .borrow_mut()
+        .context()
+        .compiler()
+        .signatures()
+        .lookup(item.type_index)
+        .expect("signature");
+    let instance_handle = unsafe { InstanceHandle::from_vmctx(item.vmctx) };
+    let export = wasmtime_runtime::Export::Function {
+        address: item.func_ptr,
+        signature,
+        vmctx: item.vmctx,
+    };
+    let f = Func::from_wasmtime_function(export, store.clone(), instance_handle, None);
+    Val::FuncRef(Rc::new(RefCell::new(f)))
+}
+
+pub struct Table {
+    store: Rc<RefCell<Store>>,
+    r#type: TableType,
+    _wasmtime_handle: InstanceHandle,
+    wasmtime_export: wasmtime_runtime::Export,
+}
+
+impl Table {
+    /// Creates a table of `funcref` or `anyref` elements, all set to `init`.
+    /// Fails if `init` is not of the element type.
+    pub fn new(store: Rc<RefCell<Store>>, r#type: TableType, init: Val) -> Result<Table, Error> {
+        if into_checked_anyfunc(init.clone(), r#type.element(), &store).is_none() {
+            failure::bail!(
+                "table of {:?} cannot be initialized with {:?}",
+                r#type.element(),
+                init.r#type()
+            );
+        }
+        let (wasmtime_handle, wasmtime_export) = generate_table_export(&r#type)?;
+        let mut table = Table {
+            store,
+            r#type,
+            _wasmtime_handle: wasmtime_handle,
+            wasmtime_export,
+        };
+        for index in 0..table.size() {
+            let success = table.set(index, init.clone());
+            assert!(success);
+        }
+        Ok(table)
+    }
+
+    pub fn r#type(&self) -> &TableType {
+        &self.r#type
+    }
+
+    fn wasmtime_table_definition(&self) -> *mut wasmtime_runtime::VMTableDefinition {
+        match self.wasmtime_export {
+            wasmtime_runtime::Export::Table { definition, .. } => definition,
+            _ => panic!("table definition not found"),
+        }
+    }
+
+    /// Returns the instance that defines the table, which is not
+    /// `_wasmtime_handle` when the table was re-exported from an import.
+    fn defining_instance(&self) -> InstanceHandle {
+        match self.wasmtime_export {
+            wasmtime_runtime::Export::Table { vmctx, .. } => unsafe {
+                InstanceHandle::from_vmctx(vmctx)
+            },
+            _ => panic!("table definition not found"),
+        }
+    }
+
+    fn wasmtime_table_index(&self, instance: &InstanceHandle) -> cranelift_wasm::DefinedTableIndex {
+        let definition = unsafe { &*self.wasmtime_table_definition() };
+        instance.table_index(definition)
+    }
+
+    /// Returns the element at `index`, or a null `anyref` when it is out of
+    /// bounds.
+    pub fn get(&self, index: u32) -> Val {
+        let instance = self.defining_instance();
+        let table_index = self.wasmtime_table_index(&instance);
+        match instance.table_get(table_index, index) {
+            Some(item) => from_checked_anyfunc(item, self.r#type.element(), &self.store),
+            None => Val::default(),
+        }
+    }
+
+    /// Sets the element at `index`; returns false when it is out of bounds or
+    /// `val` is not of the element type.
+    pub fn set(&mut self, index: u32, val: Val) -> bool {
+        let mut instance = self.defining_instance();
+        let table_index = self.wasmtime_table_index(&instance);
+        let item = match into_checked_anyfunc(val, self.r#type.element(), &self.store) {
+            Some(item) => item,
+            None => return false,
+        };
+        match instance.table_get_mut(table_index, index) {
+            Some(item_ref) => {
+                *item_ref = item;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        unsafe { (*self.wasmtime_table_definition()).current_elements }
+    }
+
+    /// Grows the table by `delta` elements set to `init`; returns false when
+    /// the table cannot grow or `init` is not of the element type.
+    pub fn grow(&mut self, delta: u32, init: Val) -> bool {
+        if into_checked_anyfunc(init.clone(), self.r#type.element(), &self.store).is_none() {
+            return false;
+        }
+        let mut instance = self.defining_instance();
+        let table_index = self.wasmtime_table_index(&instance);
+        match instance.table_grow(table_index, delta) {
+            Some(len) => {
+                for index in len - delta..len {
+                    let success = self.set(index, init.clone());
+                    assert!(success);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub(crate) fn wasmtime_export(&self) -> &wasmtime_runtime::Export {
+        &self.wasmtime_export
+    }
+
+    pub(crate) fn from_wasmtime_table(
+        export: wasmtime_runtime::Export,
+        store: Rc<RefCell<Store>>,
+        instance_handle: InstanceHandle,
+    ) -> Table {
+        let table = if let wasmtime_runtime::Export::Table { ref table, .. } = export {
+            table
+        } else {
+            panic!("wasmtime export is not table")
+        };
+        let ty = TableType::from_cranelift_table(table.table.clone());
+        Table {
+            store,
+            r#type: ty,
+            _wasmtime_handle: instance_handle,
+            wasmtime_export: export,
+        }
+    }
+}
 
 pub struct Memory {
     _store: Rc<RefCell<Store>>,
     r#type: MemoryType,
+    _wasmtime_handle: InstanceHandle,
+    wasmtime_export: wasmtime_runtime::Export,
+    _shared: Option<SharedMemory>,
 }
 
 impl Memory {
-    pub fn new(store: Rc<RefCell<Store>>, r#type: MemoryType) -> Memory {
-        Memory {
+    /// Creates a memory of the given type. A shared memory can be imported
+    /// by any number of instances of the store, and must have a maximum.
+    pub fn new(store: Rc<RefCell<Store>>, r#type: MemoryType) -> Result<Memory, Error> {
+        let (wasmtime_handle, wasmtime_export) = generate_memory_export(&r#type)?;
+        Ok(Memory {
             _store: store,
             r#type,
-        }
+            _wasmtime_handle: wasmtime_handle,
+            wasmtime_export,
+            _shared: None,
+        })
+    }
+
+    /// Creates a memory of the store backed by the `shared` memory, which
+    /// may be used by the stores of other threads at the same time.
+    pub fn from_shared(store: Rc<RefCell<Store>>, shared: &SharedMemory) -> Result<Memory, Error> {
+        let r#type = shared.r#type().clone();
+        let (wasmtime_handle, wasmtime_export) =
+            generate_shared_memory_export(&r#type, &shared.storage.pages)?;
+        Ok(Memory {
+            _store: store,
+            r#type,
+            _wasmtime_handle: wasmtime_handle,
+            wasmtime_export,
+            _shared: Some(shared.clone()),
+        })
     }
 
     pub fn r#type(&self) -> &MemoryType {
         &self.r#type
     }
 
-    pub fn data(&self) -> *const u8 {
-        unimplemented!("Memory::data")
+    fn wasmtime_memory_definition(&self) -> *mut wasmtime_runtime::VMMemoryDefinition {
+        match self.wasmtime_export {
+            wasmtime_runtime::Export::Memory { definition, .. } => definition,
+            _ => panic!("memory definition not found"),
+        }
+    }
+
+    pub fn data(&self) -> *mut u8 {
+        unsafe { (*self.wasmtime_memory_definition()).base }
     }
 
     pub fn data_size(&self) -> usize {
-        unimplemented!("Memory::data_size")
+        unsafe { (*self.wasmtime_memory_definition()).current_length }
     }
 
+    /// The size of the memory in pages.
     pub fn size(&self) -> u32 {
-        unimplemented!("Memory::size")
+        (self.data_size() / WASM_PAGE_SIZE as usize) as u32
+    }
+
+    /// Returns the instance that defines the memory, which is not
+    /// `_wasmtime_handle` when the memory was re-exported from an import.
+    fn defining_instance(&self) -> InstanceHandle {
+        match self.wasmtime_export {
+            wasmtime_runtime::Export::Memory { vmctx, .. } => unsafe {
+                InstanceHandle::from_vmctx(vmctx)
+            },
+            _ => panic!("memory definition not found"),
+        }
+    }
+
+    pub fn grow(&mut self, delta: u32) -> bool {
+        let definition = unsafe { &*self.wasmtime_memory_definition() };
+        let mut instance = self.defining_instance();
+        let index = instance.memory_index(definition);
+        instance.memory_grow(index, delta).is_some()
     }
 
-    pub fn grow(&mut self, _delta: u32) -> bool {
-        unimplemented!("Memory::grow")
+    pub(crate) fn wasmtime_export(&self) -> &wasmtime_runtime::Export {
+        &self.wasmtime_export
+    }
+
+    pub(crate) fn from_wasmtime_memory(
+        export: wasmtime_runtime::Export,
+        store: Rc<RefCell<Store>>,
+        instance_handle: InstanceHandle,
+    ) -> Memory {
+        let memory = if let wasmtime_runtime::Export::Memory { ref memory, .. } = export {
+            memory
+        } else {
+            panic!("wasmtime export is not memory")
+        };
+        let ty = MemoryType::from_cranelift_memory(memory.memory.clone());
+        Memory {
+            _store: store,
+            r#type: ty,
+            _wasmtime_handle: instance_handle,
+            wasmtime_export: export,
+            _shared: None,
+        }
+    }
+}
+
+struct SharedMemoryStorage {
+    r#type: MemoryType,
+    pages: SharedPages,
+}
+
+// The pages are never resized or moved, and are only accessed through their
+// base pointers, like a shared memory of the threads proposal.
+unsafe impl Send for SharedMemoryStorage {}
+unsafe impl Sync for SharedMemoryStorage {}
+
+/// A shared memory of the threads proposal, which can be sent to other
+/// threads and imported by instances of their stores through
+/// `Memory::from_shared`.
+///
+/// The memory has all the pages up to the maximum, which are allocated as
+/// they are first written, and never grows: instances of other stores could
+/// not observe the growth safely. Its size is always the maximum. Shared
+/// memories are only supported on Linux.
+#[derive(Clone)]
+pub struct SharedMemory {
+    storage: Arc<SharedMemoryStorage>,
+}
+
+impl SharedMemory {
+    /// Fails if the type is not shared, or has no maximum.
+    pub fn new(r#type: MemoryType) -> Result<SharedMemory, Error> {
+        if !r#type.is_shared() {
+            failure::bail!("the memory type is not shared");
+        }
+        let pages = SharedPages::new(&r#type)?;
+        Ok(SharedMemory {
+            storage: Arc::new(SharedMemoryStorage { r#type, pages }),
+        })
+    }
+
+    pub fn r#type(&self) -> &MemoryType {
+        &self.storage.r#type
+    }
+
+    pub fn data(&self) -> *mut u8 {
+        self.storage.pages.as_ptr()
+    }
+
+    pub fn data_size(&self) -> usize {
+        self.size() as usize * WASM_PAGE_SIZE as usize
+    }
+
+    /// The size of the memory in pages.
+    pub fn size(&self) -> u32 {
+        self.r#type().limits().max()
     }
 }
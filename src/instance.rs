@@ -1,13 +1,16 @@
 use crate::context::Context;
-use crate::externals::Extern;
+use crate::externals::{Extern, Func};
 use crate::module::Module;
 use crate::runtime::Store;
+use crate::trap::{FrameInfo, Trap};
+use crate::types::ExternType;
 use failure::Error;
 use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::rc::Rc;
 
-use wasmtime_jit::{instantiate, Resolver};
+use cranelift_entity::EntityRef;
+use wasmtime_jit::Resolver;
 use wasmtime_runtime::{Export, InstanceHandle};
 
 struct SimpleResolver {
@@ -25,25 +28,30 @@ impl Resolver for SimpleResolver {
 }
 
 pub fn instantiate_in_context(
-    data: &[u8],
+    module: &Module,
     imports: Vec<(String, String, Rc<RefCell<Extern>>)>,
     mut context: Context,
     exports: Rc<RefCell<HashMap<String, Option<wasmtime_runtime::Export>>>>,
 ) -> Result<(InstanceHandle, HashSet<Context>), Error> {
     let mut contexts = HashSet::new();
-    let debug_info = context.debug_info();
     let mut resolver = SimpleResolver { imports };
-    let instance = instantiate(
-        &mut context.compiler(),
-        data,
-        &mut resolver,
-        exports,
-        debug_info,
-    )?;
+    let instance =
+        module
+            .compiled()
+            .instantiate(module.binary(), &mut context, &mut resolver, exports)?;
     contexts.insert(context);
     Ok((instance, contexts))
 }
 
+fn frame_info(module: &Module, func_index: u32) -> FrameInfo {
+    let names = module.names();
+    FrameInfo::new(
+        names.module().map(str::to_string),
+        func_index,
+        names.function(func_index).map(str::to_string),
+    )
+}
+
 #[derive(Clone)]
 pub struct Instance {
     instance_handle: InstanceHandle,
@@ -55,11 +63,19 @@ pub struct Instance {
 }
 
 impl Instance {
+    /// Instantiates the `module` and runs its start function. If the start
+    /// function traps, the returned error is a `Trap`.
     pub fn new(
         store: Rc<RefCell<Store>>,
         module: Rc<RefCell<Module>>,
         externs: &[Rc<RefCell<Extern>>],
     ) -> Result<Instance, Error> {
+        // Report unsupported host functions here, rather than while linking.
+        for e in externs {
+            if let Extern::Func(f) = &*e.borrow() {
+                Func::generate_wasmtime_export(f)?;
+            }
+        }
         let context = store.borrow_mut().context().clone();
         let exports = store.borrow_mut().global_exports().clone();
         let imports = module
@@ -70,19 +86,48 @@ impl Instance {
             .map(|(i, e)| (i.module().to_string(), i.name().to_string(), e.clone()))
             .collect::<Vec<_>>();
         let (mut instance_handle, contexts) =
-            instantiate_in_context(module.borrow().binary(), imports, context, exports)?;
+            instantiate_in_context(&module.borrow(), imports, context, exports).map_err(
+                |error| match error.downcast::<Trap>() {
+                    Ok(mut trap) => {
+                        if let Some(start) = module.borrow().start() {
+                            trap.push_frame(frame_info(&module.borrow(), start));
+                        }
+                        Error::from(trap)
+                    }
+                    Err(error) => error,
+                },
+            )?;
 
         let exports = {
             let module = module.borrow();
             let mut exports = Vec::with_capacity(module.exports().len());
             for export in module.exports() {
                 let name = export.name().to_string();
-                let export = instance_handle.lookup(&name).expect("export");
-                exports.push(Rc::new(RefCell::new(Extern::from_wasmtime_export(
-                    store.clone(),
-                    instance_handle.clone(),
-                    export,
-                ))));
+                let wasmtime_export = instance_handle.lookup(&name).expect("export");
+                let ext = match export.r#type() {
+                    ExternType::ExternFunc(ty) => {
+                        Extern::Func(Rc::new(RefCell::new(Func::from_wasmtime_function(
+                            wasmtime_export,
+                            store.clone(),
+                            instance_handle.clone(),
+                            Some(ty.clone()),
+                        ))))
+                    }
+                    _ => Extern::from_wasmtime_export(
+                        store.clone(),
+                        instance_handle.clone(),
+                        wasmtime_export,
+                    ),
+                };
+                if let Extern::Func(ref func) = ext {
+                    if let Some(wasmtime_environ::Export::Function(index)) =
+                        instance_handle.module_ref().exports.get(&name)
+                    {
+                        let frame_info = frame_info(&module, index.index() as u32);
+                        func.borrow_mut().set_frame_info(frame_info);
+                    }
+                }
+                exports.push(Rc::new(RefCell::new(ext)));
             }
             exports.into_boxed_slice()
         };
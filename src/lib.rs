@@ -1,4 +1,6 @@
+mod cache;
 mod callable;
+mod compiled;
 mod context;
 mod externals;
 mod instance;
@@ -8,17 +10,29 @@ mod trampoline;
 mod trap;
 mod types;
 mod values;
+#[cfg(feature = "wat")]
+mod wat;
 
 pub mod wasm;
 
 #[macro_use]
 extern crate failure_derive;
 
+pub use crate::cache::CacheConfig;
 pub use crate::callable::Callable;
 pub use crate::externals::*;
 pub use crate::instance::Instance;
-pub use crate::module::Module;
+pub use crate::module::{CustomSection, Module, Names, ValidationError};
 pub use crate::runtime::{Config, Engine, Store};
-pub use crate::trap::Trap;
+pub use crate::trap::{FrameInfo, Trap};
 pub use crate::types::*;
 pub use crate::values::*;
+#[cfg(feature = "wat")]
+pub use crate::wat::WatError;
+
+#[allow(dead_code)]
+fn assert_send_sync() {
+    fn assert<T: Send + Sync>() {}
+    assert::<Engine>();
+    assert::<Module>();
+}
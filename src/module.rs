@@ -1,20 +1,34 @@
+use crate::cache::ModuleCacheEntry;
+use crate::compiled::CompiledModule;
+use crate::runtime::Engine;
 use crate::runtime::Store;
 use crate::types::{
     ExportType, ExternType, FuncType, GlobalType, ImportType, Limits, MemoryType, Mutability,
-    ValType,
+    TableType, ValType,
 };
 use failure::Error;
+use std::borrow::Cow;
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 use std::rc::Rc;
+use std::sync::Arc;
 
-use wasmparser::{validate, ExternalKind, ImportSectionEntryType, ModuleReader, SectionCode};
+use wasmparser::{
+    ExternalKind, ImportSectionEntryType, ModuleReader, ParserState, SectionCode, ValidatingParser,
+};
 
 fn into_memory_type(mt: wasmparser::MemoryType) -> MemoryType {
-    assert!(!mt.shared);
-    MemoryType::new(Limits::new(
+    let limits = Limits::new(
         mt.limits.initial,
         mt.limits.maximum.unwrap_or(::std::u32::MAX),
-    ))
+    );
+    if mt.shared {
+        MemoryType::new_shared(limits)
+    } else {
+        MemoryType::new(limits)
+    }
 }
 
 fn into_global_type(gt: &wasmparser::GlobalType) -> GlobalType {
@@ -33,7 +47,11 @@ fn into_valtype(ty: &wasmparser::Type) -> ValType {
         I64 => ValType::I64,
         F32 => ValType::F32,
         F64 => ValType::F64,
-        _ => unimplemented!("types in into_valtype"),
+        V128 => ValType::V128,
+        AnyFunc => ValType::FuncRef,
+        AnyRef => ValType::AnyRef,
+        // Validation only allows the types above for values.
+        _ => panic!("{:?} is not a value type", ty),
     }
 }
 
@@ -44,19 +62,177 @@ fn into_func_type(mt: wasmparser::FuncType) -> FuncType {
     FuncType::new(params.into_boxed_slice(), returns.into_boxed_slice())
 }
 
-fn read_imports_and_exports(
-    binary: &[u8],
-) -> Result<(Box<[ImportType]>, Box<[ExportType]>), Error> {
+fn into_table_type(tt: wasmparser::TableType) -> TableType {
+    let element = match tt.element_type {
+        wasmparser::Type::AnyFunc => ValType::FuncRef,
+        wasmparser::Type::AnyRef => ValType::AnyRef,
+        _ => unimplemented!("table element type in into_table_type"),
+    };
+    TableType::new(
+        element,
+        Limits::new(
+            tt.limits.initial,
+            tt.limits.maximum.unwrap_or(::std::u32::MAX),
+        ),
+    )
+}
+
+/// Reads the features the module declares in its `target_features` section,
+/// i.e. the ones prefixed with `+` (used) or `=` (required).
+fn read_target_features(section: &wasmparser::Section) -> Result<Vec<String>, Error> {
+    let mut reader = section.get_binary_reader();
+    let count = reader.read_var_u32()?;
+    let mut features = Vec::new();
+    for _ in 0..count {
+        let prefix = reader.read_u8()?;
+        let name = reader.read_string()?;
+        if prefix == u32::from(b'+') || prefix == u32::from(b'=') {
+            features.push(name.to_string());
+        }
+    }
+    Ok(features)
+}
+
+/// The first error found when validating a module.
+#[derive(Fail, Debug)]
+#[fail(display = "{} (at offset {})", message, offset)]
+pub struct ValidationError {
+    message: String,
+    offset: usize,
+}
+
+impl ValidationError {
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+    /// The offset of the error in the module binary.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+}
+
+/// A custom section of a module.
+#[derive(Debug, Clone)]
+pub struct CustomSection {
+    name: String,
+    data: Box<[u8]>,
+}
+
+impl CustomSection {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// The content of the section, without its name.
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+}
+
+/// The names decoded from the `name` custom section.
+#[derive(Debug, Clone, Default)]
+pub struct Names {
+    module: Option<String>,
+    functions: HashMap<u32, String>,
+    locals: HashMap<u32, HashMap<u32, String>>,
+}
+
+impl Names {
+    pub fn module(&self) -> Option<&str> {
+        self.module.as_ref().map(String::as_str)
+    }
+    /// The name of the function at `func_index` of the function index space.
+    pub fn function(&self, func_index: u32) -> Option<&str> {
+        self.functions.get(&func_index).map(String::as_str)
+    }
+    pub fn local(&self, func_index: u32, local_index: u32) -> Option<&str> {
+        self.locals
+            .get(&func_index)
+            .and_then(|locals| locals.get(&local_index))
+            .map(String::as_str)
+    }
+}
+
+fn read_naming_map(mut reader: wasmparser::NamingReader) -> Result<HashMap<u32, String>, Error> {
+    let mut map = HashMap::new();
+    for _ in 0..reader.get_count() {
+        let naming = reader.read()?;
+        map.insert(naming.index, naming.name.to_string());
+    }
+    Ok(map)
+}
+
+fn read_names(section: &wasmparser::Section) -> Result<Names, Error> {
+    let mut names = Names::default();
+    for name in section.get_name_section_reader()? {
+        match name? {
+            wasmparser::Name::Module(module) => {
+                names.module = Some(module.get_name()?.to_string());
+            }
+            wasmparser::Name::Function(functions) => {
+                names.functions = read_naming_map(functions.get_map()?)?;
+            }
+            wasmparser::Name::Local(locals) => {
+                let mut reader = locals.get_function_local_reader()?;
+                for _ in 0..reader.get_count() {
+                    let function = reader.read()?;
+                    names
+                        .locals
+                        .insert(function.func_index, read_naming_map(function.get_map()?)?);
+                }
+            }
+        }
+    }
+    Ok(names)
+}
+
+/// The types and definitions of a module, as read from its binary.
+struct ModuleInfo {
+    imports: Box<[ImportType]>,
+    exports: Box<[ExportType]>,
+    types: Box<[FuncType]>,
+    functions: Box<[FuncType]>,
+    tables: Box<[TableType]>,
+    memories: Box<[MemoryType]>,
+    globals: Box<[GlobalType]>,
+    start: Option<u32>,
+    data_count: usize,
+    element_count: usize,
+    features: Box<[String]>,
+    custom_sections: Box<[CustomSection]>,
+    names: Names,
+}
+
+fn read_module_info(binary: &[u8]) -> Result<ModuleInfo, Error> {
     let mut reader = ModuleReader::new(binary)?;
+    let mut start = None;
+    let mut data_count = 0;
+    let mut element_count = 0;
+    let mut features = Vec::new();
+    let mut custom_sections = Vec::new();
+    let mut names = Names::default();
     let mut imports = Vec::new();
     let mut exports = Vec::new();
+    let mut tables = Vec::new();
     let mut memories = Vec::new();
     let mut func_sig = Vec::new();
     let mut sigs = Vec::new();
     let mut globals = Vec::new();
+    // Index spaces start with the imports; the counts below separate them
+    // from the definitions.
+    let mut imported_funcs = 0;
+    let mut imported_tables = 0;
+    let mut imported_memories = 0;
+    let mut imported_globals = 0;
     while !reader.eof() {
         let section = reader.read()?;
         match section.code {
+            SectionCode::Table => {
+                let section = section.get_table_section_reader()?;
+                tables.reserve_exact(section.get_count() as usize);
+                for entry in section {
+                    tables.push(into_table_type(entry?));
+                }
+            }
             SectionCode::Memory => {
                 let section = section.get_memory_section_reader()?;
                 memories.reserve_exact(section.get_count() as usize);
@@ -73,7 +249,7 @@ fn read_imports_and_exports(
             }
             SectionCode::Function => {
                 let section = section.get_function_section_reader()?;
-                sigs.reserve_exact(section.get_count() as usize);
+                func_sig.reserve_exact(section.get_count() as usize);
                 for entry in section {
                     func_sig.push(entry?);
                 }
@@ -95,20 +271,26 @@ fn read_imports_and_exports(
                     let r#type = match entry.ty {
                         ImportSectionEntryType::Function(index) => {
                             func_sig.push(index);
+                            imported_funcs += 1;
                             let sig = &sigs[index as usize];
                             ExternType::ExternFunc(sig.clone())
                         }
-                        ImportSectionEntryType::Table(_tt) => {
-                            unimplemented!("ImportSectionEntryType::Table")
+                        ImportSectionEntryType::Table(tt) => {
+                            let table = into_table_type(tt);
+                            tables.push(table.clone());
+                            imported_tables += 1;
+                            ExternType::ExternTable(table)
                         }
                         ImportSectionEntryType::Memory(mt) => {
                             let memory = into_memory_type(mt);
                             memories.push(memory.clone());
+                            imported_memories += 1;
                             ExternType::ExternMemory(memory)
                         }
                         ImportSectionEntryType::Global(gt) => {
                             let global = into_global_type(&gt);
                             globals.push(global.clone());
+                            imported_globals += 1;
                             ExternType::ExternGlobal(global)
                         }
                     };
@@ -127,7 +309,9 @@ fn read_imports_and_exports(
                             let sig = &sigs[sig_index];
                             ExternType::ExternFunc(sig.clone())
                         }
-                        ExternalKind::Table => unimplemented!("ExternalKind::Table"),
+                        ExternalKind::Table => {
+                            ExternType::ExternTable(tables[entry.index as usize].clone())
+                        }
                         ExternalKind::Memory => {
                             ExternType::ExternMemory(memories[entry.index as usize].clone())
                         }
@@ -138,42 +322,231 @@ fn read_imports_and_exports(
                     exports.push(ExportType::new(name, r#type));
                 }
             }
+            SectionCode::Start => {
+                start = Some(section.get_start_section_content()?);
+            }
+            SectionCode::Element => {
+                element_count = section.get_element_section_reader()?.get_count() as usize;
+            }
+            SectionCode::Data => {
+                data_count = section.get_data_section_reader()?.get_count() as usize;
+            }
+            SectionCode::Custom { name, .. } => {
+                // Malformed custom sections do not invalidate the module.
+                if name == "target_features" {
+                    features = read_target_features(&section).unwrap_or_default();
+                } else if name == "name" {
+                    names = read_names(&section).unwrap_or_default();
+                }
+                let mut reader = section.get_binary_reader();
+                let data = reader.read_bytes(reader.bytes_remaining())?;
+                custom_sections.push(CustomSection {
+                    name: name.to_string(),
+                    data: data.into(),
+                });
+            }
             _ => {
                 // skip other sections
             }
         }
     }
-    Ok((imports.into_boxed_slice(), exports.into_boxed_slice()))
+    let functions = func_sig[imported_funcs..]
+        .iter()
+        .map(|index| sigs[*index as usize].clone())
+        .collect::<Vec<_>>();
+    Ok(ModuleInfo {
+        imports: imports.into_boxed_slice(),
+        exports: exports.into_boxed_slice(),
+        types: sigs.into_boxed_slice(),
+        functions: functions.into_boxed_slice(),
+        tables: tables.split_off(imported_tables).into_boxed_slice(),
+        memories: memories.split_off(imported_memories).into_boxed_slice(),
+        globals: globals.split_off(imported_globals).into_boxed_slice(),
+        start,
+        data_count,
+        element_count,
+        features: features.into_boxed_slice(),
+        custom_sections: custom_sections.into_boxed_slice(),
+        names,
+    })
 }
 
+#[cfg(feature = "wat")]
+fn to_wasm(bytes: &[u8]) -> Result<Cow<[u8]>, Error> {
+    Ok(crate::wat::to_wasm(bytes)?)
+}
+
+#[cfg(not(feature = "wat"))]
+fn to_wasm(bytes: &[u8]) -> Result<Cow<[u8]>, Error> {
+    Ok(Cow::Borrowed(bytes))
+}
+
+/// A compiled WebAssembly module. The module does not reference the `Store`
+/// it was created in: it only holds the `Engine`, so it can be cloned and sent
+/// to other threads to be instantiated in their own stores.
 #[derive(Clone)]
 pub struct Module {
-    store: Rc<RefCell<Store>>,
-    binary: Box<[u8]>,
-    imports: Box<[ImportType]>,
-    exports: Box<[ExportType]>,
+    engine: Arc<Engine>,
+    binary: Arc<[u8]>,
+    compiled: Arc<CompiledModule>,
+    info: Arc<ModuleInfo>,
 }
 
 impl Module {
+    /// Compiles the `binary` with the store's compiler, or loads it from the
+    /// engine's cache. The resulting module can be instantiated any number of
+    /// times without being recompiled. With the `wat` feature, the text
+    /// format is accepted too, and its parse errors are `WatError`s.
     pub fn new(store: Rc<RefCell<Store>>, binary: &[u8]) -> Result<Module, Error> {
-        let (imports, exports) = read_imports_and_exports(binary)?;
+        let binary = to_wasm(binary)?;
+        let binary = binary.as_ref();
+        Module::validate(&store.borrow(), binary)?;
+        let mut store = store.borrow_mut();
+        let engine = store.engine().clone();
+        let context = store.context();
+        let cache_entry = ModuleCacheEntry::new(engine.config().cache_config(), context, binary);
+        // The cache directory is trusted, see `CacheConfig::new`.
+        let cached = cache_entry.get().and_then(|serialized| {
+            unsafe { CompiledModule::deserialize(context, &serialized) }.ok()
+        });
+        let compiled = match cached {
+            Some((compiled, _)) => compiled,
+            None => {
+                let compiled = CompiledModule::new(context, binary)?;
+                cache_entry.update(|| compiled.serialize(binary));
+                compiled
+            }
+        };
+        Module::from_compiled(engine, binary.into(), compiled)
+    }
+
+    /// Reads and compiles the module in the file at `path`.
+    pub fn from_file(store: Rc<RefCell<Store>>, path: impl AsRef<Path>) -> Result<Module, Error> {
+        let bytes = fs::read(path)?;
+        Module::new(store, &bytes)
+    }
+
+    /// Loads a module previously written by `serialize` without recompiling
+    /// it. Fails if the artifact was produced by a different version of this
+    /// crate, or for a different target or code generator configuration.
+    ///
+    /// # Safety
+    ///
+    /// The artifact holds machine code that runs as is when the module is
+    /// instantiated, and the checks above only compare a header that anyone
+    /// can write. Only artifacts from a trusted source may be loaded.
+    pub unsafe fn deserialize(
+        store: Rc<RefCell<Store>>,
+        serialized: &[u8],
+    ) -> Result<Module, Error> {
+        let mut store = store.borrow_mut();
+        let (compiled, binary) = CompiledModule::deserialize(store.context(), serialized)?;
+        Module::validate(&store, &binary)?;
+        Module::from_compiled(store.engine().clone(), binary.into(), compiled)
+    }
+
+    fn from_compiled(
+        engine: Arc<Engine>,
+        binary: Arc<[u8]>,
+        compiled: CompiledModule,
+    ) -> Result<Module, Error> {
+        let info = read_module_info(&binary)?;
         Ok(Module {
-            store,
-            binary: binary.into(),
-            imports,
-            exports,
+            engine,
+            binary,
+            compiled: Arc::new(compiled),
+            info: Arc::new(info),
         })
     }
+
+    /// Returns the compiled code and metadata of the module as a versioned
+    /// artifact, which can be loaded back with `deserialize`.
+    pub fn serialize(&self) -> Vec<u8> {
+        self.compiled.serialize(&self.binary)
+    }
+    pub fn engine(&self) -> &Arc<Engine> {
+        &self.engine
+    }
     pub(crate) fn binary(&self) -> &[u8] {
         &self.binary
     }
-    pub fn validate(_store: &Store, binary: &[u8]) -> bool {
-        validate(binary, None)
+    pub(crate) fn compiled(&self) -> &Arc<CompiledModule> {
+        &self.compiled
+    }
+    /// Validates the `binary` against the features enabled in the `Config`
+    /// of the store's engine.
+    pub fn validate(store: &Store, binary: &[u8]) -> Result<(), ValidationError> {
+        let config = store.engine().config().features().clone().into();
+        let mut parser = ValidatingParser::new(binary, Some(config));
+        loop {
+            match *parser.read() {
+                ParserState::EndWasm => return Ok(()),
+                ParserState::Error(ref e) => {
+                    return Err(ValidationError {
+                        message: e.message.to_string(),
+                        offset: e.offset,
+                    });
+                }
+                _ => (),
+            }
+        }
     }
     pub fn imports(&self) -> &[ImportType] {
-        &self.imports
+        &self.info.imports
     }
     pub fn exports(&self) -> &[ExportType] {
-        &self.exports
+        &self.info.exports
+    }
+    /// The function types of the type section.
+    pub fn types(&self) -> &[FuncType] {
+        &self.info.types
+    }
+    /// The types of the functions defined (not imported) by the module.
+    pub fn functions(&self) -> &[FuncType] {
+        &self.info.functions
+    }
+    /// The tables defined (not imported) by the module.
+    pub fn tables(&self) -> &[TableType] {
+        &self.info.tables
+    }
+    /// The memories defined (not imported) by the module.
+    pub fn memories(&self) -> &[MemoryType] {
+        &self.info.memories
+    }
+    /// The globals defined (not imported) by the module.
+    pub fn globals(&self) -> &[GlobalType] {
+        &self.info.globals
+    }
+    /// The index of the start function, which runs when the module is
+    /// instantiated.
+    pub fn start(&self) -> Option<u32> {
+        self.info.start
+    }
+    pub fn data_count(&self) -> usize {
+        self.info.data_count
+    }
+    pub fn element_count(&self) -> usize {
+        self.info.element_count
+    }
+    /// The features declared in the `target_features` custom section, e.g.
+    /// "atomics" or "simd128".
+    pub fn features(&self) -> &[String] {
+        &self.info.features
+    }
+    /// The names from the `name` section, used to label traps.
+    pub fn names(&self) -> &Names {
+        &self.info.names
+    }
+    /// All custom sections, in the order they appear in the binary.
+    pub fn custom_sections(&self) -> &[CustomSection] {
+        &self.info.custom_sections
+    }
+    /// The content of the first custom section named `name`.
+    pub fn custom_section(&self, name: &str) -> Option<&[u8]> {
+        self.info
+            .custom_sections
+            .iter()
+            .find(|section| section.name() == name)
+            .map(CustomSection::data)
     }
 }
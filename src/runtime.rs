@@ -1,25 +1,29 @@
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
+use std::sync::Arc;
 
+use crate::cache::CacheConfig;
 use crate::context::Context;
+use crate::values::RefTable;
 
-use cranelift_codegen::settings;
+use cranelift_codegen::isa;
+use cranelift_codegen::settings::{self, Configurable};
+use failure::{format_err, Error};
+use target_lexicon::{Triple, HOST};
 use wasmtime_jit::Features;
 
 // Runtime Environment
 
 // Configuration
 
-fn default_flags() -> settings::Flags {
-    let flag_builder = settings::builder();
-    settings::Flags::new(flag_builder)
-}
-
 pub struct Config {
-    flags: settings::Flags,
+    flags: settings::Builder,
     features: Features,
     debug_info: bool,
+    cache_config: CacheConfig,
+    target: Option<Triple>,
+    cpu_features: Option<Vec<String>>,
 }
 
 impl Config {
@@ -27,15 +31,106 @@ impl Config {
         Config {
             debug_info: false,
             features: Default::default(),
-            flags: default_flags(),
+            flags: settings::builder(),
+            cache_config: CacheConfig::disabled(),
+            target: None,
+            cpu_features: None,
         }
     }
 
-    pub fn new(flags: settings::Flags, features: Features, debug_info: bool) -> Config {
+    pub fn new(flags: settings::Builder, features: Features, debug_info: bool) -> Config {
         Config {
             flags,
             features,
             debug_info,
+            cache_config: CacheConfig::disabled(),
+            target: None,
+            cpu_features: None,
+        }
+    }
+
+    /// Sets where and how modules compiled by the engine are cached.
+    pub fn set_cache_config(&mut self, cache_config: CacheConfig) {
+        self.cache_config = cache_config;
+    }
+
+    pub fn set_debug_info(&mut self, enable: bool) {
+        self.debug_info = enable;
+    }
+
+    /// Sets the cranelift optimization level: "default", "best" or
+    /// "fastest".
+    pub fn set_opt_level(&mut self, level: &str) -> Result<(), Error> {
+        self.flags.set("opt_level", level)?;
+        Ok(())
+    }
+
+    /// Enables the SIMD proposal and the generation of vector instructions.
+    pub fn set_wasm_simd(&mut self, enable: bool) {
+        self.features.simd = enable;
+        self.flags
+            .set("enable_simd", if enable { "true" } else { "false" })
+            .expect("enable_simd flag");
+    }
+
+    pub fn set_wasm_bulk_memory(&mut self, enable: bool) {
+        self.features.bulk_memory = enable;
+    }
+
+    pub fn set_wasm_reference_types(&mut self, enable: bool) {
+        self.features.reference_types = enable;
+    }
+
+    /// Enables the threads proposal: shared memories and atomic instructions.
+    /// The code generator does not translate atomic instructions yet, so
+    /// modules using them validate but fail to compile.
+    pub fn set_wasm_threads(&mut self, enable: bool) {
+        self.features.threads = enable;
+    }
+
+    /// Enables the multi-value proposal: functions and host callables with
+    /// more than one result. The results must fit in the target's return
+    /// registers.
+    pub fn set_wasm_multi_value(&mut self, enable: bool) {
+        self.features.multi_value = enable;
+    }
+
+    /// Generates code for `target` instead of the host. Modules compiled for
+    /// another target can be serialized, but not instantiated. The CPU
+    /// features are reset to the target's baseline.
+    pub fn set_target(&mut self, target: Triple) -> Result<(), Error> {
+        isa::lookup(target.clone())?;
+        self.target = Some(target);
+        self.cpu_features = Some(Vec::new());
+        Ok(())
+    }
+
+    /// Restricts the generated code to the baseline of the target plus the
+    /// given CPU features (e.g. "has_sse41", "has_popcnt"), instead of the
+    /// features detected on the host.
+    pub fn set_cpu_features(&mut self, cpu_features: &[&str]) -> Result<(), Error> {
+        let mut isa_builder = isa::lookup(self.target())?;
+        for feature in cpu_features {
+            isa_builder.enable(feature)?;
+        }
+        self.cpu_features = Some(cpu_features.iter().map(|f| f.to_string()).collect());
+        Ok(())
+    }
+
+    pub fn target(&self) -> Triple {
+        self.target.clone().unwrap_or(HOST)
+    }
+
+    pub(crate) fn isa_builder(&self) -> Result<isa::Builder, Error> {
+        match self.cpu_features {
+            Some(ref cpu_features) => {
+                let mut isa_builder = isa::lookup(self.target())?;
+                for feature in cpu_features {
+                    isa_builder.enable(feature)?;
+                }
+                Ok(isa_builder)
+            }
+            None => host_isa_builder(),
         }
     }
 
@@ -43,13 +138,24 @@ impl Config {
         self.debug_info
     }
 
-    pub(crate) fn flags(&self) -> &settings::Flags {
-        &self.flags
+    pub(crate) fn flags(&self) -> settings::Flags {
+        settings::Flags::new(self.flags.clone())
     }
 
     pub(crate) fn features(&self) -> &Features {
         &self.features
     }
+
+    pub(crate) fn cache_config(&self) -> &CacheConfig {
+        &self.cache_config
+    }
+}
+
+/// Returns the builder of the host's ISA, with the CPU features detected on
+/// the host enabled.
+pub(crate) fn host_isa_builder() -> Result<isa::Builder, Error> {
+    cranelift_native::builder()
+        .map_err(|message| format_err!("host machine is not a supported target: {}", message))
 }
 
 // Engine
@@ -75,27 +181,38 @@ impl Engine {
 // Store
 
 pub struct Store {
-    _engine: Rc<RefCell<Engine>>,
+    engine: Arc<Engine>,
     context: Context,
     global_exports: Rc<RefCell<HashMap<String, Option<wasmtime_runtime::Export>>>>,
+    refs: Rc<RefCell<RefTable>>,
 }
 
 impl Store {
-    pub fn new(engine: Rc<RefCell<Engine>>) -> Store {
-        let flags = engine.borrow().config().flags().clone();
-        let features = engine.borrow().config().features().clone();
-        let debug_info = engine.borrow().config().debug_info();
-        Store {
-            _engine: engine,
-            context: Context::create(flags, features, debug_info),
+    /// Fails if the engine targets the host, and the host machine is not
+    /// supported by the code generator.
+    pub fn new(engine: Arc<Engine>) -> Result<Store, Error> {
+        let context = Context::create(engine.config())?;
+        Ok(Store {
+            engine,
+            context,
             global_exports: Rc::new(RefCell::new(HashMap::new())),
-        }
+            refs: Rc::new(RefCell::new(RefTable::default())),
+        })
+    }
+
+    pub fn engine(&self) -> &Arc<Engine> {
+        &self.engine
     }
 
     pub(crate) fn context(&mut self) -> &mut Context {
         &mut self.context
     }
 
+    /// The references passed to wasm code of the store.
+    pub(crate) fn refs(&self) -> &Rc<RefCell<RefTable>> {
+        &self.refs
+    }
+
     // Specific to wasmtime: hack to pass memory around to wasi
     pub fn global_exports(
         &self,
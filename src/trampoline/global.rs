@@ -0,0 +1,69 @@
+//! Support for a creation of a global by the embedder.
+
+use super::create_handle::create_handle;
+use crate::values::RefTable;
+use crate::{GlobalType, Mutability, Val};
+use cranelift_entity::PrimaryMap;
+use failure::Error;
+use std::ptr;
+use wasmtime_environ::Module;
+use wasmtime_runtime::{InstanceHandle, VMGlobalDefinition};
+
+/// The storage of a global created by the embedder, which has room for a
+/// `v128` unlike the runtime's `VMGlobalDefinition`. Wasm code accesses
+/// imported globals through a pointer, and reads as many bytes as the type
+/// of the global needs.
+#[repr(C, align(16))]
+struct GlobalStorage([u8; 16]);
+
+/// Keeps the storage of a global created by the embedder alive.
+pub struct GlobalState {
+    #[allow(dead_code)]
+    storage: Box<GlobalStorage>,
+    #[allow(dead_code)]
+    handle: InstanceHandle,
+}
+
+pub fn create_global(
+    gt: &GlobalType,
+    val: Val,
+    refs: &mut RefTable,
+) -> Result<(wasmtime_runtime::Export, GlobalState), Error> {
+    if !val.has_type(gt.content()) {
+        failure::bail!(
+            "global of type {:?} cannot be initialized with {:?}",
+            gt.content(),
+            val.r#type()
+        );
+    }
+    let mut storage = Box::new(GlobalStorage([0; 16]));
+    let definition = storage.as_mut() as *mut GlobalStorage as *mut VMGlobalDefinition;
+    unsafe {
+        match val {
+            Val::I32(i) => *(*definition).as_i32_mut() = i,
+            Val::I64(i) => *(*definition).as_i64_mut() = i,
+            Val::F32(f) => *(*definition).as_f32_bits_mut() = f,
+            Val::F64(f) => *(*definition).as_f64_bits_mut() = f,
+            Val::V128(b) => ptr::write(definition as *mut u128, b),
+            Val::AnyRef(r) => *(*definition).as_i64_mut() = refs.anyref_into_raw(&r) as i64,
+            Val::FuncRef(f) => *(*definition).as_i64_mut() = refs.funcref_into_raw(&f) as i64,
+        }
+    }
+
+    // The handle only provides the `vmctx` of the export.
+    let mut handle = create_handle(Module::new(), PrimaryMap::new(), Box::new(()))?;
+    let export = wasmtime_runtime::Export::Global {
+        definition,
+        vmctx: handle.vmctx_mut_ptr(),
+        global: cranelift_wasm::Global {
+            ty: gt.content().get_cranelift_type(),
+            mutability: match gt.mutability() {
+                Mutability::Const => false,
+                Mutability::Var => true,
+            },
+            initializer: cranelift_wasm::GlobalInit::Import,
+        },
+    };
+    let state = GlobalState { storage, handle };
+    Ok((export, state))
+}
@@ -0,0 +1,158 @@
+//! Support for a creation of a memory by the embedder.
+
+use super::create_handle::create_handle;
+use crate::{Limits, MemoryType};
+use cranelift_entity::PrimaryMap;
+use failure::Error;
+use std::fs::File;
+use wasmtime_environ::{Export, MemoryPlan, Module, Tunables};
+use wasmtime_runtime::InstanceHandle;
+
+#[cfg(target_os = "linux")]
+use std::io;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::{AsRawFd, FromRawFd};
+#[cfg(target_os = "linux")]
+use std::ptr::{self, NonNull};
+#[cfg(target_os = "linux")]
+use wasmtime_environ::WASM_PAGE_SIZE;
+
+fn memory_plan(memory: &MemoryType) -> Result<MemoryPlan, Error> {
+    let maximum = if memory.limits().max() == ::std::u32::MAX {
+        None
+    } else {
+        Some(memory.limits().max())
+    };
+    if memory.is_shared() && maximum.is_none() {
+        failure::bail!("shared memories must have a maximum");
+    }
+    let memory = cranelift_wasm::Memory {
+        minimum: memory.limits().min(),
+        maximum,
+        shared: memory.is_shared(),
+    };
+    let tunables = Tunables::default();
+    Ok(MemoryPlan::for_memory(memory, &tunables))
+}
+
+pub fn create_handle_with_memory(memory: &MemoryType) -> Result<InstanceHandle, Error> {
+    let mut module = Module::new();
+
+    let memory_plan = memory_plan(memory)?;
+    let memory_id = module.memory_plans.push(memory_plan);
+    module
+        .exports
+        .insert("memory".to_string(), Export::Memory(memory_id));
+
+    create_handle(module, PrimaryMap::new(), Box::new(()))
+}
+
+/// The pages of a shared memory, in an anonymous file. Each store maps the
+/// file in place of the pages of its own memory, so that the runtime's
+/// memories of all the stores use the same pages.
+pub struct SharedPages {
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    file: File,
+    base: *mut u8,
+    size: usize,
+}
+
+impl SharedPages {
+    /// Creates the pages of a memory of type `memory`, up to its maximum.
+    #[cfg(target_os = "linux")]
+    pub fn new(memory: &MemoryType) -> Result<SharedPages, Error> {
+        // Fails if the memory has no maximum.
+        memory_plan(memory)?;
+        let size = memory.limits().max() as usize * WASM_PAGE_SIZE as usize;
+        let name = b"shared memory\0".as_ptr() as *const libc::c_char;
+        let fd = unsafe { libc::memfd_create(name, libc::MFD_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error().into());
+        }
+        let file = unsafe { File::from_raw_fd(fd) };
+        file.set_len(size as u64)?;
+        let base = if size == 0 {
+            NonNull::dangling().as_ptr()
+        } else {
+            unsafe { map(&file, ptr::null_mut(), size, 0)? }
+        };
+        Ok(SharedPages { file, base, size })
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    pub fn new(_memory: &MemoryType) -> Result<SharedPages, Error> {
+        failure::bail!("shared memories are only supported on Linux")
+    }
+
+    /// The pages as mapped for the embedder.
+    pub fn as_ptr(&self) -> *mut u8 {
+        self.base
+    }
+
+    /// Replaces the pages mapped at `base`, which must span the size of the
+    /// memory, with the shared ones.
+    #[cfg(target_os = "linux")]
+    unsafe fn map_at(&self, base: *mut u8) -> Result<(), Error> {
+        if self.size != 0 {
+            map(&self.file, base, self.size, libc::MAP_FIXED)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    unsafe fn map_at(&self, _base: *mut u8) -> Result<(), Error> {
+        failure::bail!("shared memories are only supported on Linux")
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Drop for SharedPages {
+    fn drop(&mut self) {
+        if self.size != 0 {
+            let result = unsafe { libc::munmap(self.base as *mut libc::c_void, self.size) };
+            assert_eq!(result, 0, "munmap failed: {}", io::Error::last_os_error());
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+unsafe fn map(
+    file: &File,
+    addr: *mut u8,
+    size: usize,
+    flags: libc::c_int,
+) -> Result<*mut u8, Error> {
+    let base = libc::mmap(
+        addr as *mut libc::c_void,
+        size,
+        libc::PROT_READ | libc::PROT_WRITE,
+        libc::MAP_SHARED | flags,
+        file.as_raw_fd(),
+        0,
+    );
+    if base == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error().into());
+    }
+    Ok(base as *mut u8)
+}
+
+/// Creates a handle whose memory has the `pages` of a shared memory. The
+/// memory is created at its maximum size, so that it never grows, and its
+/// pages are replaced before being touched: the runtime then uses the shared
+/// pages as its own, and the store allocates none.
+pub fn create_handle_with_shared_memory(
+    memory: &MemoryType,
+    pages: &SharedPages,
+) -> Result<InstanceHandle, Error> {
+    let maximum = memory.limits().max();
+    let fixed = MemoryType::new_shared(Limits::new(maximum, maximum));
+    let mut handle = create_handle_with_memory(&fixed)?;
+    match handle.lookup("memory") {
+        Some(wasmtime_runtime::Export::Memory { definition, .. }) => unsafe {
+            debug_assert_eq!((*definition).current_length, pages.size);
+            pages.map_at((*definition).base)?;
+        },
+        _ => panic!("memory export"),
+    }
+    Ok(handle)
+}
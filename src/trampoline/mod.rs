@@ -1,17 +1,64 @@
 mod code_memory;
 mod create_handle;
+mod func;
+mod global;
+mod memory;
+mod table;
 
 use failure::Error;
 use std::cell::RefCell;
 use std::rc::Rc;
 
-use self::create_handle::create_handle;
+use self::func::create_handle_with_function;
+use self::global::create_global;
+use self::memory::{create_handle_with_memory, create_handle_with_shared_memory};
+use self::table::create_handle_with_table;
 use super::externals::Func;
+use crate::values::RefTable;
+use crate::{GlobalType, MemoryType, TableType, Val};
+use wasmtime_runtime::InstanceHandle;
+
+pub(crate) use self::code_memory::CodeMemory;
+pub use self::global::GlobalState;
+pub use self::memory::SharedPages;
 
 pub fn generate_func_export(f: &Rc<RefCell<Func>>) -> Result<(), Error> {
-    let mut instance = create_handle(f)?;
+    let mut instance = create_handle_with_function(f)?;
     let export = instance.lookup("trampoline").expect("trampoline export");
 
     f.borrow_mut().anchor = Some((instance, export));
     Ok(())
 }
+
+pub fn generate_memory_export(
+    m: &MemoryType,
+) -> Result<(InstanceHandle, wasmtime_runtime::Export), Error> {
+    let mut instance = create_handle_with_memory(m)?;
+    let export = instance.lookup("memory").expect("memory export");
+    Ok((instance, export))
+}
+
+pub fn generate_shared_memory_export(
+    m: &MemoryType,
+    pages: &SharedPages,
+) -> Result<(InstanceHandle, wasmtime_runtime::Export), Error> {
+    let mut instance = create_handle_with_shared_memory(m, pages)?;
+    let export = instance.lookup("memory").expect("memory export");
+    Ok((instance, export))
+}
+
+pub fn generate_table_export(
+    t: &TableType,
+) -> Result<(InstanceHandle, wasmtime_runtime::Export), Error> {
+    let mut instance = create_handle_with_table(t)?;
+    let export = instance.lookup("table").expect("table export");
+    Ok((instance, export))
+}
+
+pub fn generate_global_export(
+    gt: &GlobalType,
+    val: Val,
+    refs: &mut RefTable,
+) -> Result<(wasmtime_runtime::Export, GlobalState), Error> {
+    create_global(gt, val, refs)
+}
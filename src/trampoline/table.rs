@@ -0,0 +1,33 @@
+//! Support for a creation of a table by the embedder.
+
+use super::create_handle::create_handle;
+use crate::TableType;
+use cranelift_entity::PrimaryMap;
+use cranelift_wasm::TableElementType;
+use failure::Error;
+use wasmtime_environ::{Export, Module, TablePlan, Tunables};
+use wasmtime_runtime::InstanceHandle;
+
+pub fn create_handle_with_table(table: &TableType) -> Result<InstanceHandle, Error> {
+    let mut module = Module::new();
+
+    let table = cranelift_wasm::Table {
+        minimum: table.limits().min(),
+        maximum: if table.limits().max() == ::std::u32::MAX {
+            None
+        } else {
+            Some(table.limits().max())
+        },
+        // The runtime only stores function elements; `Table` keeps the
+        // references of an `anyref` table in them.
+        ty: TableElementType::Func,
+    };
+    let tunables = Tunables::default();
+    let table_plan = TablePlan::for_table(table, &tunables);
+    let table_id = module.table_plans.push(table_plan);
+    module
+        .exports
+        .insert("table".to_string(), Export::Table(table_id));
+
+    create_handle(module, PrimaryMap::new(), Box::new(()))
+}
@@ -1,12 +1,59 @@
+use std::any::Any;
+use std::fmt;
+
+/// A frame of a trap's trace: a wasm function, labeled with the names from
+/// the module's name section when available.
+#[derive(Debug, Clone)]
+pub struct FrameInfo {
+    module_name: Option<String>,
+    func_index: u32,
+    func_name: Option<String>,
+}
+
+impl FrameInfo {
+    pub fn new(module_name: Option<String>, func_index: u32, func_name: Option<String>) -> Self {
+        FrameInfo {
+            module_name,
+            func_index,
+            func_name,
+        }
+    }
+
+    pub fn module_name(&self) -> Option<&str> {
+        self.module_name.as_ref().map(String::as_str)
+    }
+
+    pub fn func_index(&self) -> u32 {
+        self.func_index
+    }
+
+    pub fn func_name(&self) -> Option<&str> {
+        self.func_name.as_ref().map(String::as_str)
+    }
+}
+
+impl fmt::Display for FrameInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}!", self.module_name().unwrap_or("<module>"))?;
+        match self.func_name() {
+            Some(name) => write!(f, "{}", name),
+            None => write!(f, "<wasm function {}>", self.func_index),
+        }
+    }
+}
+
 #[derive(Fail, Debug)]
-#[fail(display = "Wasm trap")]
 pub struct Trap {
     message: String,
+    trace: Vec<FrameInfo>,
 }
 
 impl Trap {
     pub fn new(message: String) -> Trap {
-        Trap { message }
+        Trap {
+            message,
+            trace: Vec::new(),
+        }
     }
 
     pub fn fake() -> Trap {
@@ -16,4 +63,38 @@ impl Trap {
     pub fn message(&self) -> &str {
         &self.message
     }
+
+    /// The wasm function the trap was reported from: the export called by
+    /// `Func::call`, or the start function of an instance. The runtime does
+    /// not report where a trap occurred, so the functions called in between,
+    /// including the faulting one, are not part of the trace.
+    pub fn trace(&self) -> &[FrameInfo] {
+        &self.trace
+    }
+
+    pub(crate) fn push_frame(&mut self, frame: FrameInfo) {
+        self.trace.push(frame);
+    }
+}
+
+impl fmt::Display for Trap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Wasm trap: {}", self.message)?;
+        for frame in &self.trace {
+            write!(f, "\n    at {}", frame)?;
+        }
+        Ok(())
+    }
+}
+
+/// Describes a panic caught at a boundary that must not unwind, so that it
+/// can be reported as a trap.
+pub(crate) fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message.to_string()
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.clone()
+    } else {
+        "unknown panic".to_string()
+    }
 }
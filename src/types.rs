@@ -27,16 +27,26 @@ impl Limits {
             max: ::std::u32::MAX,
         }
     }
+
+    pub fn min(&self) -> u32 {
+        self.min
+    }
+
+    /// The maximum, or `u32::MAX` when unbounded.
+    pub fn max(&self) -> u32 {
+        self.max
+    }
 }
 
 // Value Types
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum ValType {
     I32,
     I64,
     F32,
     F64,
+    V128,
     AnyRef, /* = 128 */
     FuncRef,
 }
@@ -62,7 +72,9 @@ impl ValType {
             ValType::I64 => ir::types::I64,
             ValType::F32 => ir::types::F32,
             ValType::F64 => ir::types::F64,
-            _ => unimplemented!("get_cranelift_type other"),
+            ValType::V128 => ir::types::I8X16,
+            // Both kinds of references are pointers, see `RefTable`.
+            ValType::AnyRef | ValType::FuncRef => ir::types::R64,
         }
     }
 
@@ -72,6 +84,8 @@ impl ValType {
             ir::types::I64 => ValType::I64,
             ir::types::F32 => ValType::F32,
             ir::types::F64 => ValType::F64,
+            ir::types::I8X16 => ValType::V128,
+            ir::types::R32 | ir::types::R64 => ValType::AnyRef,
             _ => unimplemented!("from_cranelift_type other"),
         }
     }
@@ -237,6 +251,17 @@ impl TableType {
     pub fn limits(&self) -> &Limits {
         &self.limits
     }
+
+    pub(crate) fn from_cranelift_table(table: cranelift_wasm::Table) -> TableType {
+        let element = match table.ty {
+            cranelift_wasm::TableElementType::Func => ValType::FuncRef,
+            cranelift_wasm::TableElementType::Val(ty) => ValType::from_cranelift_type(ty),
+        };
+        TableType::new(
+            element,
+            Limits::new(table.minimum, table.maximum.unwrap_or(::std::u32::MAX)),
+        )
+    }
 }
 
 // Memory Types
@@ -244,21 +269,36 @@ impl TableType {
 #[derive(Debug, Clone)]
 pub struct MemoryType {
     limits: Limits,
+    shared: bool,
 }
 
 impl MemoryType {
     pub fn new(limits: Limits) -> MemoryType {
-        MemoryType { limits }
+        MemoryType {
+            limits,
+            shared: false,
+        }
+    }
+    /// A memory type of the threads proposal. Memories are only created for
+    /// shared types with a maximum.
+    pub fn new_shared(limits: Limits) -> MemoryType {
+        MemoryType {
+            limits,
+            shared: true,
+        }
     }
     pub fn limits(&self) -> &Limits {
         &self.limits
     }
+    pub fn is_shared(&self) -> bool {
+        self.shared
+    }
 
     pub(crate) fn from_cranelift_memory(memory: cranelift_wasm::Memory) -> MemoryType {
-        MemoryType::new(Limits::new(
-            memory.minimum,
-            memory.maximum.unwrap_or(::std::u32::MAX),
-        ))
+        MemoryType {
+            limits: Limits::new(memory.minimum, memory.maximum.unwrap_or(::std::u32::MAX)),
+            shared: memory.shared,
+        }
     }
 }
 
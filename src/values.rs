@@ -1,34 +1,112 @@
-use crate::callable::Callable;
+use crate::externals::Func;
 use crate::types::ValType;
+use std::any::Any;
 use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt;
 use std::ptr;
-use std::rc::Rc;
+use std::rc::{Rc, Weak};
 
-use cranelift_codegen::ir;
 use wasmtime_jit::RuntimeValue;
 
-#[derive(Clone)]
-pub struct AnyRef;
+/// A reference to a host object, or null. References are compared by
+/// identity of their `Rc`.
+pub struct AnyRef {
+    null: bool,
+    host_info: Option<Box<dyn Any>>,
+}
+
 impl AnyRef {
     pub fn null() -> AnyRef {
-        AnyRef
+        AnyRef {
+            null: true,
+            host_info: None,
+        }
+    }
+
+    /// Creates a non-null reference to a new host object.
+    pub fn new(host_info: Option<Box<dyn Any>>) -> AnyRef {
+        AnyRef {
+            null: false,
+            host_info,
+        }
+    }
+
+    pub fn is_null(&self) -> bool {
+        self.null
+    }
+
+    pub fn host_info(&self) -> Option<&dyn Any> {
+        self.host_info.as_ref().map(|info| info.as_ref())
+    }
+
+    /// Attaches `info` to the object, dropping the previous one.
+    pub fn set_host_info(&mut self, info: Option<Box<dyn Any>>) {
+        assert!(!self.null, "null reference has no host info");
+        self.host_info = info;
     }
 }
 
 impl fmt::Debug for AnyRef {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "anyref")
+        if self.null {
+            write!(f, "null")
+        } else {
+            write!(f, "anyref")
+        }
     }
 }
 
-pub struct FuncRef {
-    pub callable: Box<dyn Callable + 'static>,
+/// The references a store has passed to wasm code, by the pointer that
+/// represents them there. Nothing traces the copies wasm code makes, so each
+/// reference is kept alive until the store is dropped; passing it again does
+/// not add to its count.
+///
+/// Functions are the exception: they hold their store, so they are only
+/// held weakly, and a reference to a function that was dropped since is
+/// not found. The weak reference keeps the allocation, which keeps the
+/// pointer from being reused.
+#[derive(Default)]
+pub(crate) struct RefTable {
+    anyrefs: HashMap<usize, Rc<RefCell<AnyRef>>>,
+    funcrefs: HashMap<usize, Weak<RefCell<Func>>>,
 }
 
-impl fmt::Debug for FuncRef {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "funcref")
+impl RefTable {
+    /// Converts a reference into the pointer that represents it in wasm code.
+    pub(crate) fn anyref_into_raw(&mut self, r: &Rc<RefCell<AnyRef>>) -> usize {
+        if r.borrow().is_null() {
+            return 0;
+        }
+        let p = &**r as *const RefCell<AnyRef> as usize;
+        self.anyrefs.entry(p).or_insert_with(|| r.clone());
+        p
+    }
+
+    /// Converts a function reference into the pointer that represents it in
+    /// wasm code.
+    pub(crate) fn funcref_into_raw(&mut self, f: &Rc<RefCell<Func>>) -> usize {
+        let p = &**f as *const RefCell<Func> as usize;
+        self.funcrefs.entry(p).or_insert_with(|| Rc::downgrade(f));
+        p
+    }
+
+    /// Converts a pointer made by the methods above back into the reference,
+    /// if it is one of type `ty`. Returns `None` for any other pointer, which
+    /// wasm code can pass where the two reference types were confused, e.g.
+    /// by a mismatched import.
+    pub(crate) fn from_raw(&self, p: usize, ty: &ValType) -> Option<Val> {
+        if p == 0 {
+            return Some(Val::default());
+        }
+        if let Some(f) = self.funcrefs.get(&p) {
+            // A function reference is also an `anyref`.
+            return f.upgrade().map(Val::FuncRef);
+        }
+        match ty {
+            ValType::AnyRef => self.anyrefs.get(&p).cloned().map(Val::AnyRef),
+            _ => None,
+        }
     }
 }
 
@@ -38,8 +116,9 @@ pub enum Val {
     I64(i64),
     F32(u32),
     F64(u64),
+    V128(u128),
     AnyRef(Rc<RefCell<AnyRef>>),
-    FuncRef(Rc<RefCell<FuncRef>>),
+    FuncRef(Rc<RefCell<Func>>),
 }
 
 impl Val {
@@ -53,29 +132,55 @@ impl Val {
             Val::I64(_) => ValType::I64,
             Val::F32(_) => ValType::F32,
             Val::F64(_) => ValType::F64,
+            Val::V128(_) => ValType::V128,
             Val::AnyRef(_) => ValType::AnyRef,
             Val::FuncRef(_) => ValType::FuncRef,
         }
     }
 
-    pub(crate) unsafe fn write_value_to(&self, p: *mut i64) {
+    /// Whether the value can be stored where a `ty` is expected. A function
+    /// reference is also an `anyref`, and a null `anyref` is also the null
+    /// reference of the other reference types.
+    pub(crate) fn has_type(&self, ty: &ValType) -> bool {
+        match (self, ty) {
+            (Val::FuncRef(_), ValType::AnyRef) => true,
+            (Val::AnyRef(r), ValType::FuncRef) => r.borrow().is_null(),
+            (val, ty) => val.r#type() == *ty,
+        }
+    }
+
+    /// Writes the value to a slot of the values vector used by the
+    /// trampolines; each slot is wide enough for a `v128`. References are
+    /// kept alive by `refs`.
+    pub(crate) unsafe fn write_value_to(&self, p: *mut u128, refs: &mut RefTable) {
         match self {
             Val::I32(i) => ptr::write(p as *mut i32, *i),
             Val::I64(i) => ptr::write(p as *mut i64, *i),
             Val::F32(u) => ptr::write(p as *mut u32, *u),
             Val::F64(u) => ptr::write(p as *mut u64, *u),
-            _ => unimplemented!("Val::write_value_to"),
+            Val::V128(b) => ptr::write(p, *b),
+            Val::AnyRef(r) => ptr::write(p as *mut usize, refs.anyref_into_raw(r)),
+            Val::FuncRef(f) => ptr::write(p as *mut usize, refs.funcref_into_raw(f)),
         }
     }
 
-    pub(crate) unsafe fn read_value_from(p: *const i64, ty: ir::Type) -> Val {
-        match ty {
-            ir::types::I32 => Val::I32(ptr::read(p as *const i32)),
-            ir::types::I64 => Val::I64(ptr::read(p as *const i64)),
-            ir::types::F32 => Val::F32(ptr::read(p as *const u32)),
-            ir::types::F64 => Val::F64(ptr::read(p as *const u64)),
-            _ => unimplemented!("Val::read_value_from"),
-        }
+    /// Reads a value of type `ty` from a slot of the values vector. Returns
+    /// `None` for a reference that `refs` does not hold with that type.
+    pub(crate) unsafe fn read_value_from(
+        p: *const u128,
+        ty: &ValType,
+        refs: &RefTable,
+    ) -> Option<Val> {
+        Some(match ty {
+            ValType::I32 => Val::I32(ptr::read(p as *const i32)),
+            ValType::I64 => Val::I64(ptr::read(p as *const i64)),
+            ValType::F32 => Val::F32(ptr::read(p as *const u32)),
+            ValType::F64 => Val::F64(ptr::read(p as *const u64)),
+            ValType::V128 => Val::V128(ptr::read(p)),
+            ValType::AnyRef | ValType::FuncRef => {
+                return refs.from_raw(ptr::read(p as *const usize), ty);
+            }
+        })
     }
 
     pub fn from_f32_bits(v: u32) -> Val {
@@ -111,6 +216,12 @@ impl From<f64> for Val {
     }
 }
 
+impl From<u128> for Val {
+    fn from(val: u128) -> Val {
+        Val::V128(val)
+    }
+}
+
 impl Into<i32> for Val {
     fn into(self) -> i32 {
         if let Val::I32(i) = self {
@@ -151,14 +262,24 @@ impl Into<f64> for Val {
     }
 }
 
+impl Into<u128> for Val {
+    fn into(self) -> u128 {
+        if let Val::V128(b) = self {
+            b
+        } else {
+            panic!("Invalid conversion of {:?} to u128.", self);
+        }
+    }
+}
+
 impl From<Rc<RefCell<AnyRef>>> for Val {
     fn from(val: Rc<RefCell<AnyRef>>) -> Val {
         Val::AnyRef(val)
     }
 }
 
-impl From<Rc<RefCell<FuncRef>>> for Val {
-    fn from(val: Rc<RefCell<FuncRef>>) -> Val {
+impl From<Rc<RefCell<Func>>> for Val {
+    fn from(val: Rc<RefCell<Func>>) -> Val {
         Val::FuncRef(val)
     }
 }
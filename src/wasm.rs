@@ -4,15 +4,22 @@
 #![allow(non_snake_case, non_camel_case_types, non_upper_case_globals)]
 
 use super::{
-    Callable, Engine, ExportType, Extern, Func, FuncType, ImportType, Instance, Module, Store,
-    Trap, Val, ValType,
+    AnyRef, CacheConfig, Callable, Config, Engine, ExportType, Extern, ExternType, FrameInfo, Func,
+    FuncType, Global, GlobalType, ImportType, Instance, Limits, Memory, MemoryType, Module,
+    Mutability, Store, Table, TableType, Trap, Val, ValType,
 };
+use crate::trap::panic_message;
+use std::any::Any;
 use std::boxed::Box;
 use std::cell::RefCell;
+use std::ffi::CStr;
 use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
 use std::ptr;
 use std::rc::Rc;
 use std::slice;
+use std::sync::Arc;
 
 pub type byte_t = ::std::os::raw::c_char;
 pub type float32_t = f32;
@@ -26,14 +33,13 @@ pub struct wasm_byte_vec_t {
 }
 pub type wasm_name_t = wasm_byte_vec_t;
 #[repr(C)]
-#[derive(Clone)]
 pub struct wasm_config_t {
-    _unused: [u8; 0],
+    config: Config,
 }
 #[repr(C)]
 #[derive(Clone)]
 pub struct wasm_engine_t {
-    engine: Rc<RefCell<Engine>>,
+    engine: Arc<Engine>,
 }
 #[repr(C)]
 #[derive(Clone)]
@@ -61,9 +67,10 @@ pub struct wasm_valtype_vec_t {
 }
 pub type wasm_valkind_t = u8;
 #[repr(C)]
-#[derive(Clone)]
 pub struct wasm_functype_t {
     functype: FuncType,
+    params_cache: wasm_valtype_vec_t,
+    returns_cache: wasm_valtype_vec_t,
 }
 #[repr(C)]
 #[derive(Clone)]
@@ -74,7 +81,8 @@ pub struct wasm_functype_vec_t {
 #[repr(C)]
 #[derive(Clone)]
 pub struct wasm_globaltype_t {
-    _unused: [u8; 0],
+    globaltype: GlobalType,
+    content_cache: wasm_valtype_t,
 }
 #[repr(C)]
 #[derive(Clone)]
@@ -85,7 +93,9 @@ pub struct wasm_globaltype_vec_t {
 #[repr(C)]
 #[derive(Clone)]
 pub struct wasm_tabletype_t {
-    _unused: [u8; 0],
+    tabletype: TableType,
+    element_cache: wasm_valtype_t,
+    limits_cache: wasm_limits_t,
 }
 #[repr(C)]
 #[derive(Clone)]
@@ -96,7 +106,8 @@ pub struct wasm_tabletype_vec_t {
 #[repr(C)]
 #[derive(Clone)]
 pub struct wasm_memorytype_t {
-    _unused: [u8; 0],
+    memorytype: MemoryType,
+    limits_cache: wasm_limits_t,
 }
 #[repr(C)]
 #[derive(Clone)]
@@ -107,7 +118,15 @@ pub struct wasm_memorytype_vec_t {
 #[repr(C)]
 #[derive(Clone)]
 pub struct wasm_externtype_t {
-    _unused: [u8; 0],
+    ty: ExternType,
+    cache: wasm_externtype_t_type_cache,
+}
+#[derive(Clone)]
+enum wasm_externtype_t_type_cache {
+    Func(wasm_functype_t),
+    Global(wasm_globaltype_t),
+    Memory(wasm_memorytype_t),
+    Table(wasm_tabletype_t),
 }
 #[repr(C)]
 #[derive(Clone)]
@@ -117,9 +136,11 @@ pub struct wasm_externtype_vec_t {
 }
 pub type wasm_externkind_t = u8;
 #[repr(C)]
-#[derive(Clone)]
 pub struct wasm_importtype_t {
     ty: ImportType,
+    module_cache: wasm_name_t,
+    name_cache: wasm_name_t,
+    type_cache: wasm_externtype_t,
 }
 #[repr(C)]
 #[derive(Clone)]
@@ -128,9 +149,10 @@ pub struct wasm_importtype_vec_t {
     pub data: *mut *mut wasm_importtype_t,
 }
 #[repr(C)]
-#[derive(Clone)]
 pub struct wasm_exporttype_t {
     ty: ExportType,
+    name_cache: wasm_name_t,
+    type_cache: wasm_externtype_t,
 }
 #[repr(C)]
 #[derive(Clone)]
@@ -142,7 +164,7 @@ pub struct wasm_exporttype_vec_t {
 #[repr(C)]
 #[derive(Clone)]
 pub struct wasm_ref_t {
-    _unused: [u8; 0],
+    r: Val,
 }
 #[repr(C)]
 #[derive(Copy, Clone)]
@@ -169,7 +191,7 @@ pub struct wasm_val_vec_t {
 #[repr(C)]
 #[derive(Clone)]
 pub struct wasm_frame_t {
-    _unused: [u8; 0],
+    frame: FrameInfo,
 }
 #[repr(C)]
 #[derive(Clone)]
@@ -191,7 +213,7 @@ pub struct wasm_trap_t {
 #[repr(C)]
 #[derive(Clone)]
 pub struct wasm_foreign_t {
-    _unused: [u8; 0],
+    anyref: Rc<RefCell<AnyRef>>,
 }
 #[repr(C)]
 #[derive(Clone)]
@@ -203,7 +225,7 @@ pub struct wasm_module_t {
 #[repr(C)]
 #[derive(Clone)]
 pub struct wasm_shared_module_t {
-    _unused: [u8; 0],
+    module: Module,
 }
 #[repr(C)]
 #[derive(Clone)]
@@ -223,18 +245,18 @@ pub type wasm_func_callback_with_env_t = ::std::option::Option<
 #[repr(C)]
 #[derive(Clone)]
 pub struct wasm_global_t {
-    _unused: [u8; 0],
+    global: Rc<RefCell<Global>>,
 }
 #[repr(C)]
 #[derive(Clone)]
 pub struct wasm_table_t {
-    _unused: [u8; 0],
+    table: Rc<RefCell<Table>>,
 }
 pub type wasm_table_size_t = u32;
 #[repr(C)]
 #[derive(Clone)]
 pub struct wasm_memory_t {
-    _unused: [u8; 0],
+    memory: Rc<RefCell<Memory>>,
 }
 pub type wasm_memory_pages_t = u32;
 #[repr(C)]
@@ -249,18 +271,245 @@ pub struct wasm_extern_vec_t {
     pub data: *mut *mut wasm_extern_t,
 }
 
-#[no_mangle]
-pub unsafe extern "C" fn wasm_byte_vec_delete(v: *mut wasm_byte_vec_t) {
-    let _ = Vec::from_raw_parts((*v).data, 0, (*v).size);
+/// Declares the `new_empty`, `new_uninitialized`, `new`, `copy` and
+/// `delete` functions of a vector type. A vector owns its elements: `new`
+/// takes them over, `copy` copies them with `copy_elem`, and `delete`
+/// releases them with `delete_elem`.
+macro_rules! declare_vec {
+    (
+        $vec:ident<$elem:ty> {
+            new_empty: $new_empty:ident,
+            new_uninitialized: $new_uninitialized:ident,
+            new: $new:ident,
+            copy: $copy:ident,
+            delete: $delete:ident,
+            default: $default:expr,
+            copy_elem: $copy_elem:expr,
+            delete_elem: $delete_elem:expr,
+        }
+    ) => {
+        impl $vec {
+            fn set_buffer(&mut self, buffer: Vec<$elem>) {
+                let mut buffer = buffer.into_boxed_slice();
+                self.size = buffer.len();
+                self.data = buffer.as_mut_ptr();
+                mem::forget(buffer);
+            }
+
+            fn as_slice(&self) -> &[$elem] {
+                if self.size == 0 {
+                    &[]
+                } else {
+                    unsafe { slice::from_raw_parts(self.data, self.size) }
+                }
+            }
+
+            fn take(&mut self) -> Vec<$elem> {
+                if self.data.is_null() {
+                    return Vec::new();
+                }
+                let buffer = unsafe { Vec::from_raw_parts(self.data, self.size, self.size) };
+                self.size = 0;
+                self.data = ptr::null_mut();
+                buffer
+            }
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $new_empty(out: *mut $vec) {
+            (*out).size = 0;
+            (*out).data = ptr::null_mut();
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $new_uninitialized(out: *mut $vec, size: usize) {
+            (*out).set_buffer(vec![$default; size]);
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $new(out: *mut $vec, size: usize, data: *const $elem) {
+            if size == 0 {
+                $new_empty(out);
+                return;
+            }
+            (*out).set_buffer(slice::from_raw_parts(data, size).to_vec());
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $copy(out: *mut $vec, src: *const $vec) {
+            let copy_elem = $copy_elem;
+            (*out).set_buffer((*src).as_slice().iter().map(copy_elem).collect());
+        }
+
+        #[no_mangle]
+        pub unsafe extern "C" fn $delete(v: *mut $vec) {
+            let delete_elem = $delete_elem;
+            for elem in (*v).take() {
+                delete_elem(elem);
+            }
+        }
+    };
 }
 
-#[no_mangle]
-pub unsafe extern "C" fn wasm_byte_vec_new_uninitialized(out: *mut wasm_byte_vec_t, size: usize) {
-    let mut buffer = vec![0; size];
-    let result = out.as_mut().unwrap();
-    result.size = buffer.capacity();
-    result.data = buffer.as_mut_ptr();
-    mem::forget(buffer);
+/// Declares a vector of boxed objects that are cloned by `copy` and
+/// dropped by `delete`.
+macro_rules! declare_own_vec {
+    (
+        $vec:ident<$elem:ident> {
+            new_empty: $new_empty:ident,
+            new_uninitialized: $new_uninitialized:ident,
+            new: $new:ident,
+            copy: $copy:ident,
+            delete: $delete:ident,
+        }
+    ) => {
+        declare_vec!($vec<*mut $elem> {
+            new_empty: $new_empty,
+            new_uninitialized: $new_uninitialized,
+            new: $new,
+            copy: $copy,
+            delete: $delete,
+            default: ptr::null_mut(),
+            copy_elem: |e: &*mut $elem| {
+                if e.is_null() {
+                    ptr::null_mut()
+                } else {
+                    Box::into_raw(Box::new((**e).clone()))
+                }
+            },
+            delete_elem: |e: *mut $elem| {
+                if !e.is_null() {
+                    let _ = Box::from_raw(e);
+                }
+            },
+        });
+    };
+}
+
+declare_vec!(wasm_byte_vec_t<wasm_byte_t> {
+    new_empty: wasm_byte_vec_new_empty,
+    new_uninitialized: wasm_byte_vec_new_uninitialized,
+    new: wasm_byte_vec_new,
+    copy: wasm_byte_vec_copy,
+    delete: wasm_byte_vec_delete,
+    default: 0,
+    copy_elem: |b: &wasm_byte_t| *b,
+    delete_elem: |_: wasm_byte_t| (),
+});
+
+impl wasm_byte_vec_t {
+    fn as_bytes(&self) -> &[u8] {
+        let bytes = self.as_slice();
+        unsafe { slice::from_raw_parts(bytes.as_ptr() as *const u8, bytes.len()) }
+    }
+}
+
+declare_vec!(wasm_val_vec_t<wasm_val_t> {
+    new_empty: wasm_val_vec_new_empty,
+    new_uninitialized: wasm_val_vec_new_uninitialized,
+    new: wasm_val_vec_new,
+    copy: wasm_val_vec_copy,
+    delete: wasm_val_vec_delete,
+    default: wasm_val_t::default(),
+    copy_elem: |v: &wasm_val_t| {
+        let mut copy = wasm_val_t::default();
+        wasm_val_copy(&mut copy, v);
+        copy
+    },
+    delete_elem: |mut v: wasm_val_t| wasm_val_delete(&mut v),
+});
+
+declare_own_vec!(wasm_valtype_vec_t<wasm_valtype_t> {
+    new_empty: wasm_valtype_vec_new_empty,
+    new_uninitialized: wasm_valtype_vec_new_uninitialized,
+    new: wasm_valtype_vec_new,
+    copy: wasm_valtype_vec_copy,
+    delete: wasm_valtype_vec_delete,
+});
+
+declare_own_vec!(wasm_functype_vec_t<wasm_functype_t> {
+    new_empty: wasm_functype_vec_new_empty,
+    new_uninitialized: wasm_functype_vec_new_uninitialized,
+    new: wasm_functype_vec_new,
+    copy: wasm_functype_vec_copy,
+    delete: wasm_functype_vec_delete,
+});
+
+declare_own_vec!(wasm_globaltype_vec_t<wasm_globaltype_t> {
+    new_empty: wasm_globaltype_vec_new_empty,
+    new_uninitialized: wasm_globaltype_vec_new_uninitialized,
+    new: wasm_globaltype_vec_new,
+    copy: wasm_globaltype_vec_copy,
+    delete: wasm_globaltype_vec_delete,
+});
+
+declare_own_vec!(wasm_tabletype_vec_t<wasm_tabletype_t> {
+    new_empty: wasm_tabletype_vec_new_empty,
+    new_uninitialized: wasm_tabletype_vec_new_uninitialized,
+    new: wasm_tabletype_vec_new,
+    copy: wasm_tabletype_vec_copy,
+    delete: wasm_tabletype_vec_delete,
+});
+
+declare_own_vec!(wasm_memorytype_vec_t<wasm_memorytype_t> {
+    new_empty: wasm_memorytype_vec_new_empty,
+    new_uninitialized: wasm_memorytype_vec_new_uninitialized,
+    new: wasm_memorytype_vec_new,
+    copy: wasm_memorytype_vec_copy,
+    delete: wasm_memorytype_vec_delete,
+});
+
+declare_own_vec!(wasm_externtype_vec_t<wasm_externtype_t> {
+    new_empty: wasm_externtype_vec_new_empty,
+    new_uninitialized: wasm_externtype_vec_new_uninitialized,
+    new: wasm_externtype_vec_new,
+    copy: wasm_externtype_vec_copy,
+    delete: wasm_externtype_vec_delete,
+});
+
+declare_own_vec!(wasm_importtype_vec_t<wasm_importtype_t> {
+    new_empty: wasm_importtype_vec_new_empty,
+    new_uninitialized: wasm_importtype_vec_new_uninitialized,
+    new: wasm_importtype_vec_new,
+    copy: wasm_importtype_vec_copy,
+    delete: wasm_importtype_vec_delete,
+});
+
+declare_own_vec!(wasm_exporttype_vec_t<wasm_exporttype_t> {
+    new_empty: wasm_exporttype_vec_new_empty,
+    new_uninitialized: wasm_exporttype_vec_new_uninitialized,
+    new: wasm_exporttype_vec_new,
+    copy: wasm_exporttype_vec_copy,
+    delete: wasm_exporttype_vec_delete,
+});
+
+declare_own_vec!(wasm_frame_vec_t<wasm_frame_t> {
+    new_empty: wasm_frame_vec_new_empty,
+    new_uninitialized: wasm_frame_vec_new_uninitialized,
+    new: wasm_frame_vec_new,
+    copy: wasm_frame_vec_copy,
+    delete: wasm_frame_vec_delete,
+});
+
+declare_own_vec!(wasm_extern_vec_t<wasm_extern_t> {
+    new_empty: wasm_extern_vec_new_empty,
+    new_uninitialized: wasm_extern_vec_new_uninitialized,
+    new: wasm_extern_vec_new,
+    copy: wasm_extern_vec_copy,
+    delete: wasm_extern_vec_delete,
+});
+
+/// Runs `f`, returning `default` if it panics: unwinding across an
+/// `extern "C"` function is undefined behavior.
+fn catch_panic<T>(default: T, f: impl FnOnce() -> T) -> T {
+    panic::catch_unwind(AssertUnwindSafe(f)).unwrap_or(default)
+}
+
+fn new_trap(message: String) -> *mut wasm_trap_t {
+    let trap = Box::new(wasm_trap_t {
+        trap: Rc::new(RefCell::new(Trap::new(message))),
+    });
+    Box::into_raw(trap)
 }
 
 #[no_mangle]
@@ -271,27 +520,144 @@ pub unsafe extern "C" fn wasm_engine_delete(engine: *mut wasm_engine_t) {
 #[no_mangle]
 pub unsafe extern "C" fn wasm_engine_new() -> *mut wasm_engine_t {
     let engine = Box::new(wasm_engine_t {
-        engine: Rc::new(RefCell::new(Engine::default())),
+        engine: Arc::new(Engine::default()),
+    });
+    Box::into_raw(engine)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_engine_new_with_config(c: *mut wasm_config_t) -> *mut wasm_engine_t {
+    let config = Box::from_raw(c).config;
+    let engine = Box::new(wasm_engine_t {
+        engine: Arc::new(Engine::new(config)),
     });
     Box::into_raw(engine)
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn wasm_config_new() -> *mut wasm_config_t {
+    let config = Box::new(wasm_config_t {
+        config: Config::default(),
+    });
+    Box::into_raw(config)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_config_delete(c: *mut wasm_config_t) {
+    let _ = Box::from_raw(c);
+}
+
+// Vendor extensions of wasm_config_t.
+
+#[no_mangle]
+pub unsafe extern "C" fn wasmtime_config_debug_info_set(c: *mut wasm_config_t, enable: bool) {
+    (*c).config.set_debug_info(enable);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasmtime_config_wasm_threads_set(c: *mut wasm_config_t, enable: bool) {
+    (*c).config.set_wasm_threads(enable);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasmtime_config_wasm_simd_set(c: *mut wasm_config_t, enable: bool) {
+    (*c).config.set_wasm_simd(enable);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasmtime_config_wasm_multi_value_set(c: *mut wasm_config_t, enable: bool) {
+    (*c).config.set_wasm_multi_value(enable);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasmtime_config_wasm_bulk_memory_set(c: *mut wasm_config_t, enable: bool) {
+    (*c).config.set_wasm_bulk_memory(enable);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasmtime_config_wasm_reference_types_set(
+    c: *mut wasm_config_t,
+    enable: bool,
+) {
+    (*c).config.set_wasm_reference_types(enable);
+}
+
+/// Sets the optimization level from a C string; returns false when the
+/// level is unknown.
+#[no_mangle]
+pub unsafe extern "C" fn wasmtime_config_cranelift_opt_level_set(
+    c: *mut wasm_config_t,
+    level: *const ::std::os::raw::c_char,
+) -> bool {
+    let level = CStr::from_ptr(level).to_string_lossy();
+    (*c).config.set_opt_level(&level).is_ok()
+}
+
+/// Enables the compilation cache in the directory named by a C string.
+#[no_mangle]
+pub unsafe extern "C" fn wasmtime_config_cache_dir_set(
+    c: *mut wasm_config_t,
+    directory: *const ::std::os::raw::c_char,
+) {
+    let directory = CStr::from_ptr(directory).to_string_lossy().into_owned();
+    (*c).config
+        .set_cache_config(CacheConfig::new(PathBuf::from(directory)));
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn wasm_extern_as_func(e: *mut wasm_extern_t) -> *mut wasm_func_t {
-    let func = (*e).ext.borrow().func().clone();
-    let func = Box::new(wasm_func_t { func });
-    Box::into_raw(func)
+    match &*(*e).ext.borrow() {
+        Extern::Func(func) => Box::into_raw(Box::new(wasm_func_t { func: func.clone() })),
+        _ => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_extern_as_global(e: *mut wasm_extern_t) -> *mut wasm_global_t {
+    match &*(*e).ext.borrow() {
+        Extern::Global(global) => Box::into_raw(Box::new(wasm_global_t {
+            global: global.clone(),
+        })),
+        _ => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_extern_as_memory(e: *mut wasm_extern_t) -> *mut wasm_memory_t {
+    match &*(*e).ext.borrow() {
+        Extern::Memory(memory) => Box::into_raw(Box::new(wasm_memory_t {
+            memory: memory.clone(),
+        })),
+        _ => ptr::null_mut(),
+    }
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn wasm_extern_vec_delete(v: *mut wasm_extern_vec_t) {
-    let buffer = Vec::from_raw_parts((*v).data, (*v).size, (*v).size);
-    for p in buffer {
-        // TODO wasm_extern_delete
-        let _ = Box::from_raw(p);
+pub unsafe extern "C" fn wasm_extern_as_table(e: *mut wasm_extern_t) -> *mut wasm_table_t {
+    match &*(*e).ext.borrow() {
+        Extern::Table(table) => Box::into_raw(Box::new(wasm_table_t {
+            table: table.clone(),
+        })),
+        _ => ptr::null_mut(),
     }
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn wasm_extern_delete(e: *mut wasm_extern_t) {
+    let _ = Box::from_raw(e);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_extern_kind(e: *const wasm_extern_t) -> wasm_externkind_t {
+    from_externtype(&(*e).ext.borrow().r#type())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_extern_type(e: *const wasm_extern_t) -> *mut wasm_externtype_t {
+    let et = Box::new(wasm_externtype_t::new((*e).ext.borrow().r#type()));
+    Box::into_raw(et)
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn wasm_func_as_extern(f: *mut wasm_func_t) -> *mut wasm_extern_t {
     let ext = Extern::Func((*f).func.clone());
@@ -307,24 +673,36 @@ pub unsafe extern "C" fn wasm_func_call(
     args: *const wasm_val_t,
     results: *mut wasm_val_t,
 ) -> *mut wasm_trap_t {
-    let func = (*func).func.borrow();
-    let mut params = Vec::with_capacity(func.param_arity());
-    for i in 0..func.param_arity() {
-        let val = &(*args.offset(i as isize));
-        params.push(val.val());
-    }
-    match func.call(&params) {
-        Ok(out) => {
-            for i in 0..func.result_arity() {
-                let val = &mut (*results.offset(i as isize));
-                *val = wasm_val_t::from_val(&out[i]);
+    let call = || {
+        let func = (*func).func.borrow();
+        let mut params = Vec::with_capacity(func.param_arity());
+        for i in 0..func.param_arity() {
+            match (*args.offset(i as isize)).val() {
+                Some(val) => params.push(val),
+                None => return new_trap(format!("invalid kind of argument {}", i)),
             }
-            ptr::null_mut()
         }
-        Err(trap) => {
-            let trap = Box::new(wasm_trap_t { trap });
-            Box::into_raw(trap)
+        if func.r#type().results().contains(&ValType::V128) {
+            return new_trap("v128 results cannot be returned as a wasm_val_t".to_string());
+        }
+        match func.call(&params) {
+            Ok(out) => {
+                for (i, out) in out.iter().enumerate() {
+                    if let Some(out) = wasm_val_t::from_val(out) {
+                        *results.offset(i as isize) = out;
+                    }
+                }
+                ptr::null_mut()
+            }
+            Err(trap) => {
+                let trap = Box::new(wasm_trap_t { trap });
+                Box::into_raw(trap)
+            }
         }
+    };
+    match panic::catch_unwind(AssertUnwindSafe(call)) {
+        Ok(trap) => trap,
+        Err(payload) => new_trap(panic_message(payload)),
     }
 }
 
@@ -336,41 +714,92 @@ impl wasm_val_t {
         }
     }
 
-    fn from_val(val: &Val) -> wasm_val_t {
-        match val {
-            Val::I32(i) => wasm_val_t {
-                kind: from_valtype(ValType::I32),
-                of: wasm_val_t__bindgen_ty_1 { i32: *i },
+    /// Converts `val`; a reference is boxed into a new `wasm_ref_t` owned
+    /// by the result. Returns `None` for a `v128`, which does not fit in the
+    /// value.
+    fn from_val(val: &Val) -> Option<wasm_val_t> {
+        let of = match val {
+            Val::I32(i) => wasm_val_t__bindgen_ty_1 { i32: *i },
+            Val::I64(i) => wasm_val_t__bindgen_ty_1 { i64: *i },
+            Val::F32(u) => wasm_val_t__bindgen_ty_1 {
+                f32: f32::from_bits(*u),
             },
-            _ => unimplemented!("wasm_val_t::from_val {:?}", val),
-        }
+            Val::F64(u) => wasm_val_t__bindgen_ty_1 {
+                f64: f64::from_bits(*u),
+            },
+            Val::AnyRef(_) | Val::FuncRef(_) => wasm_val_t__bindgen_ty_1 {
+                ref_: from_ref_val(val.clone()),
+            },
+            Val::V128(_) => return None,
+        };
+        Some(wasm_val_t {
+            kind: from_valtype(val.r#type()),
+            of,
+        })
     }
 
-    fn val(&self) -> Val {
-        match into_valtype(self.kind) {
+    /// Converts the value without taking ownership of its reference; a null
+    /// reference is a null `anyref`. Returns `None` for an unknown kind.
+    fn val(&self) -> Option<Val> {
+        Some(match into_valtype(self.kind)? {
             ValType::I32 => Val::from(unsafe { self.of.i32 }),
-            _ => unimplemented!("wasm_val_t::val {:?}", self.kind),
+            ValType::I64 => Val::from(unsafe { self.of.i64 }),
+            ValType::F32 => Val::from(unsafe { self.of.f32 }),
+            ValType::F64 => Val::from(unsafe { self.of.f64 }),
+            ValType::AnyRef | ValType::FuncRef => unsafe { into_ref_val(self.of.ref_) },
+            ValType::V128 => return None,
+        })
+    }
+}
+
+/// Calls a C callback with the `params` converted to `wasm_val_t`s, and
+/// converts back the results it wrote or the trap it returned.
+fn call_callback(
+    params: &[Val],
+    results: &mut [Val],
+    callback: impl FnOnce(*const wasm_val_t, *mut wasm_val_t) -> *mut wasm_trap_t,
+) -> Result<(), Rc<RefCell<Trap>>> {
+    // Host functions with `v128` values are rejected when created.
+    let params = match params
+        .iter()
+        .map(wasm_val_t::from_val)
+        .collect::<Option<Vec<_>>>()
+    {
+        Some(params) => params,
+        None => {
+            let message = "v128 parameters cannot be passed as a wasm_val_t".to_string();
+            return Err(Rc::new(RefCell::new(Trap::new(message))));
+        }
+    };
+    let mut out_results = vec![wasm_val_t::default(); results.len()];
+    let out = callback(params.as_ptr(), out_results.as_mut_ptr());
+    for mut param in params {
+        unsafe { wasm_val_delete(&mut param) };
+    }
+    if out != ptr::null_mut() {
+        let trap: Box<wasm_trap_t> = unsafe { Box::from_raw(out) };
+        return Err((*trap).into());
+    }
+    for (result, mut out_result) in results.iter_mut().zip(out_results) {
+        let val = out_result.val();
+        unsafe { wasm_val_delete(&mut out_result) };
+        match val {
+            Some(val) => *result = val,
+            None => {
+                let message = "callback returned a value of invalid kind".to_string();
+                return Err(Rc::new(RefCell::new(Trap::new(message))));
+            }
         }
     }
+    Ok(())
 }
 
 impl Callable for wasm_func_callback_t {
     fn call(&self, params: &[Val], results: &mut [Val]) -> Result<(), Rc<RefCell<Trap>>> {
-        let params = params
-            .iter()
-            .map(|p| wasm_val_t::from_val(p))
-            .collect::<Vec<_>>();
-        let mut out_results = vec![wasm_val_t::default(); results.len()];
         let func = self.expect("wasm_func_callback_t fn");
-        let out = unsafe { func(params.as_ptr(), out_results.as_mut_ptr()) };
-        if out != ptr::null_mut() {
-            let trap: Box<wasm_trap_t> = unsafe { Box::from_raw(out) };
-            return Err((*trap).into());
-        }
-        for i in 0..results.len() {
-            results[i] = out_results[i].val();
-        }
-        Ok(())
+        call_callback(params, results, |params, results| unsafe {
+            func(params, results)
+        })
     }
 }
 
@@ -388,21 +817,10 @@ struct CallbackWithEnv {
 
 impl Callable for CallbackWithEnv {
     fn call(&self, params: &[Val], results: &mut [Val]) -> Result<(), Rc<RefCell<Trap>>> {
-        let params = params
-            .iter()
-            .map(|p| wasm_val_t::from_val(p))
-            .collect::<Vec<_>>();
-        let mut out_results = vec![wasm_val_t::default(); results.len()];
         let func = self.callback.expect("wasm_func_callback_with_env_t fn");
-        let out = unsafe { func(self.env, params.as_ptr(), out_results.as_mut_ptr()) };
-        if out != ptr::null_mut() {
-            let trap: Box<wasm_trap_t> = unsafe { Box::from_raw(out) };
-            return Err((*trap).into());
-        }
-        for i in 0..results.len() {
-            results[i] = out_results[i].val();
-        }
-        Ok(())
+        call_callback(params, results, |params, results| unsafe {
+            func(self.env, params, results)
+        })
     }
 }
 
@@ -416,12 +834,33 @@ impl Drop for CallbackWithEnv {
     }
 }
 
+/// Checks that every parameter and result of a host function fits in a
+/// `wasm_val_t`, so that calling the callback cannot fail.
+fn is_callback_type(ty: &FuncType) -> bool {
+    ty.params()
+        .iter()
+        .chain(ty.results())
+        .all(|ty| *ty != ValType::V128)
+}
+
+/// Whether every value type in `ty` has a `wasm_valkind_t`.
+fn has_value_kinds(ty: &ExternType) -> bool {
+    match ty {
+        ExternType::ExternFunc(ft) => is_callback_type(ft),
+        ExternType::ExternGlobal(gt) => *gt.content() != ValType::V128,
+        ExternType::ExternTable(_) | ExternType::ExternMemory(_) => true,
+    }
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn wasm_func_new(
     store: *mut wasm_store_t,
     ty: *const wasm_functype_t,
     callback: wasm_func_callback_t,
 ) -> *mut wasm_func_t {
+    if callback.is_none() || !is_callback_type(&(*ty).functype) {
+        return ptr::null_mut();
+    }
     let store = (*store).store.clone();
     let ty = (*ty).functype.clone();
     let callback = Rc::new(callback);
@@ -441,16 +880,18 @@ pub unsafe extern "C" fn wasm_functype_new(
     params: *mut wasm_valtype_vec_t,
     results: *mut wasm_valtype_vec_t,
 ) -> *mut wasm_functype_t {
-    let params = Vec::from_raw_parts((*params).data, (*params).size, (*params).size)
+    let params = (*params)
+        .take()
         .into_iter()
-        .map(|vt| (*vt).ty.clone())
+        .map(|vt| Box::from_raw(vt).ty)
         .collect::<Vec<_>>();
-    let results = Vec::from_raw_parts((*results).data, (*results).size, (*results).size)
+    let results = (*results)
+        .take()
         .into_iter()
-        .map(|vt| (*vt).ty.clone())
+        .map(|vt| Box::from_raw(vt).ty)
         .collect::<Vec<_>>();
     let functype = FuncType::new(params.into_boxed_slice(), results.into_boxed_slice());
-    let functype = Box::new(wasm_functype_t { functype });
+    let functype = Box::new(wasm_functype_t::new(functype));
     Box::into_raw(functype)
 }
 
@@ -459,6 +900,80 @@ pub unsafe extern "C" fn wasm_functype_delete(ft: *mut wasm_functype_t) {
     let _ = Box::from_raw(ft);
 }
 
+impl wasm_functype_t {
+    // The vectors are built up front, since the accessors only get a
+    // `*const wasm_functype_t`.
+    fn new(functype: FuncType) -> wasm_functype_t {
+        let params_cache = into_valtype_vec(functype.params());
+        let returns_cache = into_valtype_vec(functype.results());
+        wasm_functype_t {
+            functype,
+            params_cache,
+            returns_cache,
+        }
+    }
+}
+
+impl Clone for wasm_functype_t {
+    fn clone(&self) -> wasm_functype_t {
+        wasm_functype_t::new(self.functype.clone())
+    }
+}
+
+impl Drop for wasm_functype_t {
+    fn drop(&mut self) {
+        unsafe {
+            wasm_valtype_vec_delete(&mut self.params_cache);
+            wasm_valtype_vec_delete(&mut self.returns_cache);
+        }
+    }
+}
+
+fn into_valtype_vec(types: &[ValType]) -> wasm_valtype_vec_t {
+    let mut vec = wasm_valtype_vec_t {
+        size: 0,
+        data: ptr::null_mut(),
+    };
+    vec.set_buffer(
+        types
+            .iter()
+            .map(|ty| Box::into_raw(Box::new(wasm_valtype_t { ty: ty.clone() })))
+            .collect(),
+    );
+    vec
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_functype_params(
+    ft: *const wasm_functype_t,
+) -> *const wasm_valtype_vec_t {
+    &(*ft).params_cache
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_functype_results(
+    ft: *const wasm_functype_t,
+) -> *const wasm_valtype_vec_t {
+    &(*ft).returns_cache
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_func_type(f: *const wasm_func_t) -> *mut wasm_functype_t {
+    let functype = (*f).func.borrow().r#type().clone();
+    let ft = Box::new(wasm_functype_t::new(functype));
+    Box::into_raw(ft)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_func_param_arity(f: *const wasm_func_t) -> usize {
+    (*f).func.borrow().param_arity()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_func_result_arity(f: *const wasm_func_t) -> usize {
+    (*f).func.borrow().result_arity()
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn wasm_instance_delete(instance: *mut wasm_instance_t) {
     let _ = Box::from_raw(instance);
@@ -469,8 +984,11 @@ pub unsafe extern "C" fn wasm_instance_new(
     store: *mut wasm_store_t,
     module: *const wasm_module_t,
     imports: *const *const wasm_extern_t,
-    _result: *mut *mut wasm_trap_t,
+    result: *mut *mut wasm_trap_t,
 ) -> *mut wasm_instance_t {
+    if !result.is_null() {
+        (*result) = ptr::null_mut();
+    }
     let store = (*store).store.clone();
     let mut externs: Vec<Rc<RefCell<Extern>>> = Vec::with_capacity((*module).imports.len());
     for i in 0..(*module).imports.len() {
@@ -478,14 +996,39 @@ pub unsafe extern "C" fn wasm_instance_new(
         externs.push((*import).ext.clone());
     }
     let module = (*module).module.clone();
-    match Instance::new(store, module, &externs) {
+    let instance = panic::catch_unwind(AssertUnwindSafe(|| Instance::new(store, module, &externs)));
+    let instance = match instance {
+        Ok(instance) => instance,
+        Err(payload) => {
+            if !result.is_null() {
+                (*result) = new_trap(panic_message(payload));
+            }
+            return ptr::null_mut();
+        }
+    };
+    match instance {
         Ok(instance) => {
             let instance = Box::new(wasm_instance_t {
                 instance: Rc::new(RefCell::new(instance)),
             });
             Box::into_raw(instance)
         }
-        _ => unimplemented!(),
+        Err(error) => {
+            if !result.is_null() {
+                (*result) = match error.downcast::<Trap>() {
+                    Ok(trap) => {
+                        let trap = Box::new(wasm_trap_t {
+                            trap: Rc::new(RefCell::new(trap)),
+                        });
+                        Box::into_raw(trap)
+                    }
+                    // Link errors are reported as traps too, since the API
+                    // has no other way to describe them.
+                    Err(error) => new_trap(error.to_string()),
+                };
+            }
+            ptr::null_mut()
+        }
     }
 }
 
@@ -501,9 +1044,7 @@ pub unsafe extern "C" fn wasm_instance_exports(
         let ext = Box::new(wasm_extern_t { ext: e.clone() });
         buffer.push(Box::into_raw(ext));
     }
-    (*out).size = buffer.capacity();
-    (*out).data = buffer.as_mut_ptr();
-    mem::forget(buffer);
+    (*out).set_buffer(buffer);
 }
 
 #[no_mangle]
@@ -516,27 +1057,140 @@ pub unsafe extern "C" fn wasm_module_new(
     store: *mut wasm_store_t,
     binary: *const wasm_byte_vec_t,
 ) -> *mut wasm_module_t {
-    let binary = slice::from_raw_parts((*binary).data as *const u8, (*binary).size);
+    let binary = (*binary).as_bytes();
+    let store = (*store).store.clone();
+    catch_panic(ptr::null_mut(), || {
+        match Module::new(store, binary).ok().and_then(wasm_module_t::new) {
+            Some(module) => Box::into_raw(Box::new(module)),
+            None => ptr::null_mut(),
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_module_validate(
+    store: *mut wasm_store_t,
+    binary: *const wasm_byte_vec_t,
+) -> bool {
+    let binary = (*binary).as_bytes();
+    catch_panic(false, || {
+        Module::validate(&(*store).store.borrow(), binary).is_ok()
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_module_serialize(
+    module: *const wasm_module_t,
+    out: *mut wasm_byte_vec_t,
+) {
+    let serialized = (*module).module.borrow().serialize();
+    (*out).set_buffer(serialized.into_iter().map(|b| b as wasm_byte_t).collect());
+}
+
+/// Returns null when the bytes were not produced by `wasm_module_serialize`
+/// of a compatible engine. The bytes hold machine code that runs as is, so
+/// they must come from a trusted source: a crafted artifact can pass the
+/// checks, see `Module::deserialize`.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_module_deserialize(
+    store: *mut wasm_store_t,
+    serialized: *const wasm_byte_vec_t,
+) -> *mut wasm_module_t {
+    let serialized = (*serialized).as_bytes();
     let store = (*store).store.clone();
-    let module = Module::new(store, binary).expect("module");
-    let imports = module
-        .imports()
+    catch_panic(ptr::null_mut(), || {
+        match Module::deserialize(store, serialized)
+            .ok()
+            .and_then(wasm_module_t::new)
+        {
+            Some(module) => Box::into_raw(Box::new(module)),
+            None => ptr::null_mut(),
+        }
+    })
+}
+
+impl wasm_module_t {
+    /// Returns `None` if the types of the imports or exports cannot be
+    /// described: `wasm.h` has no value kind for `v128`.
+    fn new(module: Module) -> Option<wasm_module_t> {
+        let types = module.imports().iter().map(ImportType::r#type);
+        if !types
+            .chain(module.exports().iter().map(ExportType::r#type))
+            .all(has_value_kinds)
+        {
+            return None;
+        }
+        let imports = module
+            .imports()
+            .iter()
+            .map(|i| wasm_importtype_t::new(i.clone()))
+            .collect::<Vec<_>>();
+        let exports = module
+            .exports()
+            .iter()
+            .map(|e| wasm_exporttype_t::new(e.clone()))
+            .collect::<Vec<_>>();
+        Some(wasm_module_t {
+            module: Rc::new(RefCell::new(module)),
+            imports,
+            exports,
+        })
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_module_imports(
+    module: *const wasm_module_t,
+    out: *mut wasm_importtype_vec_t,
+) {
+    let buffer = (*module)
+        .imports
         .iter()
-        .map(|i| wasm_importtype_t { ty: i.clone() })
-        .collect::<Vec<_>>();
-    let exports = module
-        .exports()
+        .map(|i| Box::into_raw(Box::new(wasm_importtype_t::new(i.ty.clone()))))
+        .collect();
+    (*out).set_buffer(buffer);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_module_exports(
+    module: *const wasm_module_t,
+    out: *mut wasm_exporttype_vec_t,
+) {
+    let buffer = (*module)
+        .exports
         .iter()
-        .map(|e| wasm_exporttype_t { ty: e.clone() })
-        .collect::<Vec<_>>();
-    let module = Box::new(wasm_module_t {
-        module: Rc::new(RefCell::new(module)),
-        imports,
-        exports,
-    });
+        .map(|e| Box::into_raw(Box::new(wasm_exporttype_t::new(e.ty.clone()))))
+        .collect();
+    (*out).set_buffer(buffer);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_module_share(
+    module: *const wasm_module_t,
+) -> *mut wasm_shared_module_t {
+    let module = (*module).module.borrow().clone();
+    let shared_module = Box::new(wasm_shared_module_t { module });
+    Box::into_raw(shared_module)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_module_obtain(
+    store: *mut wasm_store_t,
+    shared_module: *const wasm_shared_module_t,
+) -> *mut wasm_module_t {
+    let module = &(*shared_module).module;
+    if !Arc::ptr_eq((*store).store.borrow().engine(), module.engine()) {
+        return ptr::null_mut();
+    }
+    let module = Box::new(wasm_module_t::new(module.clone()));
     Box::into_raw(module)
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn wasm_shared_module_delete(shared_module: *mut wasm_shared_module_t) {
+    let _ = Box::from_raw(shared_module);
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn wasm_store_delete(store: *mut wasm_store_t) {
     let _ = Box::from_raw(store);
@@ -545,16 +1199,12 @@ pub unsafe extern "C" fn wasm_store_delete(store: *mut wasm_store_t) {
 #[no_mangle]
 pub unsafe extern "C" fn wasm_store_new(engine: *mut wasm_engine_t) -> *mut wasm_store_t {
     let engine = (*engine).engine.clone();
-    let store = Box::new(wasm_store_t {
-        store: Rc::new(RefCell::new(Store::new(engine))),
-    });
-    Box::into_raw(store)
-}
-
-#[no_mangle]
-pub unsafe extern "C" fn wasm_valtype_vec_new_empty(out: *mut wasm_valtype_vec_t) {
-    (*out).data = ptr::null_mut();
-    (*out).size = 0;
+    match Store::new(engine) {
+        Ok(store) => Box::into_raw(Box::new(wasm_store_t {
+            store: Rc::new(RefCell::new(store)),
+        })),
+        Err(_) => ptr::null_mut(),
+    }
 }
 
 #[no_mangle]
@@ -565,6 +1215,9 @@ pub unsafe extern "C" fn wasm_func_new_with_env(
     env: *mut ::std::os::raw::c_void,
     finalizer: ::std::option::Option<unsafe extern "C" fn(arg1: *mut ::std::os::raw::c_void)>,
 ) -> *mut wasm_func_t {
+    if callback.is_none() || !is_callback_type(&(*ty).functype) {
+        return ptr::null_mut();
+    }
     let store = (*store).store.clone();
     let ty = (*ty).functype.clone();
     let callback = Rc::new(CallbackWithEnv {
@@ -581,21 +1234,41 @@ pub unsafe extern "C" fn wasm_func_new_with_env(
 #[no_mangle]
 pub unsafe extern "C" fn wasm_val_copy(out: *mut wasm_val_t, source: *const wasm_val_t) {
     *out = match into_valtype((*source).kind) {
-        ValType::I32 | ValType::I64 | ValType::F32 | ValType::F64 => (*source).clone(),
-        _ => unimplemented!("wasm_val_copy arg"),
+        // The copy owns a new `wasm_ref_t`, and keeps the kind of the source
+        // even when the reference is null.
+        Some(ValType::AnyRef) | Some(ValType::FuncRef) => wasm_val_t {
+            kind: (*source).kind,
+            of: wasm_val_t__bindgen_ty_1 {
+                ref_: from_ref_val(into_ref_val((*source).of.ref_)),
+            },
+        },
+        _ => (*source).clone(),
     };
 }
 
-fn into_valtype(kind: wasm_valkind_t) -> ValType {
-    match kind {
+#[no_mangle]
+pub unsafe extern "C" fn wasm_val_delete(val: *mut wasm_val_t) {
+    match into_valtype((*val).kind) {
+        Some(ValType::AnyRef) | Some(ValType::FuncRef) => {
+            if !(*val).of.ref_.is_null() {
+                let _ = Box::from_raw((*val).of.ref_);
+                (*val).of.ref_ = ptr::null_mut();
+            }
+        }
+        _ => (),
+    }
+}
+
+fn into_valtype(kind: wasm_valkind_t) -> Option<ValType> {
+    Some(match kind {
         0 => ValType::I32,
         1 => ValType::I64,
         2 => ValType::F32,
         3 => ValType::F64,
         128 => ValType::AnyRef,
         129 => ValType::FuncRef,
-        _ => panic!("unexpected kind: {}", kind),
-    }
+        _ => return None,
+    })
 }
 
 fn from_valtype(ty: ValType) -> wasm_valkind_t {
@@ -604,79 +1277,56 @@ fn from_valtype(ty: ValType) -> wasm_valkind_t {
         ValType::I64 => 1,
         ValType::F32 => 2,
         ValType::F64 => 3,
+        // `wasm_module_new` rejects modules with `v128` in the types of
+        // their imports or exports, and no other type of the C API has one.
+        ValType::V128 => unreachable!("v128 has no value kind"),
         ValType::AnyRef => 128,
         ValType::FuncRef => 129,
     }
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn wasm_valtype_new(kind: wasm_valkind_t) -> *mut wasm_valtype_t {
-    let ty = Box::new(wasm_valtype_t {
-        ty: into_valtype(kind),
-    });
-    Box::into_raw(ty)
+pub unsafe extern "C" fn wasm_valtype_delete(vt: *mut wasm_valtype_t) {
+    let _ = Box::from_raw(vt);
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn wasm_valtype_vec_new(
-    out: *mut wasm_valtype_vec_t,
-    size: usize,
-    data: *const *mut wasm_valtype_t,
-) {
-    let slice = slice::from_raw_parts(data, size);
-    let mut buffer = Vec::with_capacity(size);
-    buffer.extend_from_slice(slice);
-    assert!(size == buffer.capacity());
-    (*out).size = size;
-    (*out).data = buffer.as_mut_ptr();
-    mem::forget(buffer);
-}
-#[no_mangle]
-pub unsafe extern "C" fn wasm_byte_vec_new(
-    out: *mut wasm_byte_vec_t,
-    size: usize,
-    data: *const wasm_byte_t,
-) {
-    let slice = slice::from_raw_parts(data, size);
-    let mut buffer = Vec::with_capacity(size);
-    buffer.extend_from_slice(slice);
-    assert!(size == buffer.capacity());
-    (*out).size = size;
-    (*out).data = buffer.as_mut_ptr();
-    mem::forget(buffer);
+pub unsafe extern "C" fn wasm_valtype_kind(vt: *const wasm_valtype_t) -> wasm_valkind_t {
+    from_valtype((*vt).ty.clone())
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn wasm_frame_delete(_arg1: *mut wasm_frame_t) {
-    unimplemented!("wasm_frame_delete")
+pub unsafe extern "C" fn wasm_valtype_new(kind: wasm_valkind_t) -> *mut wasm_valtype_t {
+    match into_valtype(kind) {
+        Some(ty) => Box::into_raw(Box::new(wasm_valtype_t { ty })),
+        None => ptr::null_mut(),
+    }
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn wasm_frame_func_index(_arg1: *const wasm_frame_t) -> u32 {
-    unimplemented!("wasm_frame_func_index")
+pub unsafe extern "C" fn wasm_frame_delete(frame: *mut wasm_frame_t) {
+    let _ = Box::from_raw(frame);
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn wasm_frame_func_offset(_arg1: *const wasm_frame_t) -> usize {
-    unimplemented!("wasm_frame_func_offset")
+pub unsafe extern "C" fn wasm_frame_func_index(frame: *const wasm_frame_t) -> u32 {
+    (*frame).frame.func_index()
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn wasm_frame_instance(_arg1: *const wasm_frame_t) -> *mut wasm_instance_t {
-    unimplemented!("wasm_frame_instance")
+pub unsafe extern "C" fn wasm_frame_func_offset(_frame: *const wasm_frame_t) -> usize {
+    // Code offsets are not tracked for trap frames.
+    0
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn wasm_frame_module_offset(_arg1: *const wasm_frame_t) -> usize {
-    unimplemented!("wasm_frame_module_offset")
+pub unsafe extern "C" fn wasm_frame_instance(_frame: *const wasm_frame_t) -> *mut wasm_instance_t {
+    ptr::null_mut()
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn wasm_frame_vec_delete(frames: *mut wasm_frame_vec_t) {
-    let frames = Vec::from_raw_parts((*frames).data, (*frames).size, (*frames).size);
-    for _frame in frames {
-        unimplemented!("wasm_frame_vec_delete for frame")
-    }
+pub unsafe extern "C" fn wasm_frame_module_offset(_frame: *const wasm_frame_t) -> usize {
+    0
 }
 
 #[no_mangle]
@@ -689,38 +1339,759 @@ pub unsafe extern "C" fn wasm_trap_new(
     _store: *mut wasm_store_t,
     message: *const wasm_message_t,
 ) -> *mut wasm_trap_t {
-    let message = slice::from_raw_parts((*message).data as *const u8, (*message).size);
-    if message[message.len() - 1] != 0 {
-        panic!("wasm_trap_new message stringz expected");
+    let mut message = (*message).as_bytes();
+    // The message is expected to be NUL-terminated, but one without the
+    // terminator is taken as is.
+    if let Some((0, rest)) = message.split_last() {
+        message = rest;
     }
-    let message = String::from_utf8_lossy(message).to_string();
-    let trap = Box::new(wasm_trap_t {
-        trap: Rc::new(RefCell::new(Trap::new(message))),
-    });
-    Box::into_raw(trap)
+    new_trap(String::from_utf8_lossy(message).to_string())
 }
 
 #[no_mangle]
 pub unsafe extern "C" fn wasm_trap_message(trap: *const wasm_trap_t, out: *mut wasm_message_t) {
     let mut buffer = Vec::new();
     buffer.extend_from_slice((*trap).trap.borrow().message().as_bytes());
-    buffer.reserve_exact(1);
     buffer.push(0);
-    assert!(buffer.len() == buffer.capacity());
-    (*out).size = buffer.capacity();
-    (*out).data = buffer.as_mut_ptr() as *mut i8;
-    mem::forget(buffer);
+    (*out).set_buffer(buffer.into_iter().map(|b| b as wasm_byte_t).collect());
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn wasm_trap_origin(_trap: *const wasm_trap_t) -> *mut wasm_frame_t {
-    ptr::null_mut()
+pub unsafe extern "C" fn wasm_trap_origin(trap: *const wasm_trap_t) -> *mut wasm_frame_t {
+    match (*trap).trap.borrow().trace().first() {
+        Some(frame) => {
+            let frame = Box::new(wasm_frame_t {
+                frame: frame.clone(),
+            });
+            Box::into_raw(frame)
+        }
+        None => ptr::null_mut(),
+    }
 }
 
 #[no_mangle]
-pub unsafe extern "C" fn wasm_trap_trace(_trap: *const wasm_trap_t, out: *mut wasm_frame_vec_t) {
-    let mut buffer = Vec::new();
-    (*out).size = 0;
-    (*out).data = buffer.as_mut_ptr();
-    mem::forget(buffer);
+pub unsafe extern "C" fn wasm_trap_trace(trap: *const wasm_trap_t, out: *mut wasm_frame_vec_t) {
+    let buffer = (*trap)
+        .trap
+        .borrow()
+        .trace()
+        .iter()
+        .map(|frame| {
+            Box::into_raw(Box::new(wasm_frame_t {
+                frame: frame.clone(),
+            }))
+        })
+        .collect();
+    (*out).set_buffer(buffer);
+}
+
+impl wasm_globaltype_t {
+    fn new(globaltype: GlobalType) -> wasm_globaltype_t {
+        let content_cache = wasm_valtype_t {
+            ty: globaltype.content().clone(),
+        };
+        wasm_globaltype_t {
+            globaltype,
+            content_cache,
+        }
+    }
+}
+
+fn into_mutability(mutability: wasm_mutability_t) -> Option<Mutability> {
+    match mutability {
+        0 => Some(Mutability::Const),
+        1 => Some(Mutability::Var),
+        _ => None,
+    }
+}
+
+fn from_mutability(mutability: Mutability) -> wasm_mutability_t {
+    match mutability {
+        Mutability::Const => 0,
+        Mutability::Var => 1,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_globaltype_new(
+    ty: *mut wasm_valtype_t,
+    mutability: wasm_mutability_t,
+) -> *mut wasm_globaltype_t {
+    let ty = Box::from_raw(ty);
+    let mutability = match into_mutability(mutability) {
+        Some(mutability) => mutability,
+        None => return ptr::null_mut(),
+    };
+    let gt = Box::new(wasm_globaltype_t::new(GlobalType::new(ty.ty, mutability)));
+    Box::into_raw(gt)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_globaltype_delete(gt: *mut wasm_globaltype_t) {
+    let _ = Box::from_raw(gt);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_globaltype_content(
+    gt: *const wasm_globaltype_t,
+) -> *const wasm_valtype_t {
+    &(*gt).content_cache
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_globaltype_mutability(
+    gt: *const wasm_globaltype_t,
+) -> wasm_mutability_t {
+    from_mutability((*gt).globaltype.mutability())
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_global_new(
+    store: *mut wasm_store_t,
+    gt: *const wasm_globaltype_t,
+    val: *const wasm_val_t,
+) -> *mut wasm_global_t {
+    let val = match (*val).val() {
+        Some(val) => val,
+        None => return ptr::null_mut(),
+    };
+    let store = (*store).store.clone();
+    let globaltype = (*gt).globaltype.clone();
+    catch_panic(ptr::null_mut(), || {
+        match Global::new(store, globaltype, val) {
+            Ok(global) => Box::into_raw(Box::new(wasm_global_t {
+                global: Rc::new(RefCell::new(global)),
+            })),
+            Err(_) => ptr::null_mut(),
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_global_delete(g: *mut wasm_global_t) {
+    let _ = Box::from_raw(g);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_global_type(g: *const wasm_global_t) -> *mut wasm_globaltype_t {
+    let globaltype = (*g).global.borrow().r#type().clone();
+    let gt = Box::new(wasm_globaltype_t::new(globaltype));
+    Box::into_raw(gt)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_global_get(g: *const wasm_global_t, out: *mut wasm_val_t) {
+    // `out` holds a defined value even if the read fails.
+    *out = wasm_val_t::default();
+    catch_panic((), || {
+        if let Some(val) = wasm_val_t::from_val(&(*g).global.borrow().get()) {
+            *out = val;
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_global_set(g: *mut wasm_global_t, val: *const wasm_val_t) {
+    if let Some(val) = (*val).val() {
+        // The C API has no way to report the error; the global is unchanged.
+        catch_panic((), || {
+            let _ = (*g).global.borrow_mut().set(val);
+        })
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_global_as_extern(g: *mut wasm_global_t) -> *mut wasm_extern_t {
+    let ext = Extern::Global((*g).global.clone());
+    let ext = Box::new(wasm_extern_t {
+        ext: Rc::new(RefCell::new(ext)),
+    });
+    Box::into_raw(ext)
+}
+
+impl wasm_memorytype_t {
+    fn new(memorytype: MemoryType) -> wasm_memorytype_t {
+        let limits_cache = wasm_limits_t {
+            min: memorytype.limits().min(),
+            max: memorytype.limits().max(),
+        };
+        wasm_memorytype_t {
+            memorytype,
+            limits_cache,
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_memorytype_new(
+    limits: *const wasm_limits_t,
+) -> *mut wasm_memorytype_t {
+    let limits = Limits::new((*limits).min, (*limits).max);
+    let mt = Box::new(wasm_memorytype_t::new(MemoryType::new(limits)));
+    Box::into_raw(mt)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_memorytype_delete(mt: *mut wasm_memorytype_t) {
+    let _ = Box::from_raw(mt);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_memorytype_limits(
+    mt: *const wasm_memorytype_t,
+) -> *const wasm_limits_t {
+    &(*mt).limits_cache
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_memory_new(
+    store: *mut wasm_store_t,
+    mt: *const wasm_memorytype_t,
+) -> *mut wasm_memory_t {
+    let store = (*store).store.clone();
+    let memorytype = (*mt).memorytype.clone();
+    catch_panic(ptr::null_mut(), || match Memory::new(store, memorytype) {
+        Ok(memory) => Box::into_raw(Box::new(wasm_memory_t {
+            memory: Rc::new(RefCell::new(memory)),
+        })),
+        Err(_) => ptr::null_mut(),
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_memory_delete(m: *mut wasm_memory_t) {
+    let _ = Box::from_raw(m);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_memory_type(m: *const wasm_memory_t) -> *mut wasm_memorytype_t {
+    let memorytype = (*m).memory.borrow().r#type().clone();
+    let mt = Box::new(wasm_memorytype_t::new(memorytype));
+    Box::into_raw(mt)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_memory_data(m: *mut wasm_memory_t) -> *mut byte_t {
+    (*m).memory.borrow().data() as *mut byte_t
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_memory_data_size(m: *const wasm_memory_t) -> usize {
+    (*m).memory.borrow().data_size()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_memory_size(m: *const wasm_memory_t) -> wasm_memory_pages_t {
+    (*m).memory.borrow().size()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_memory_grow(
+    m: *mut wasm_memory_t,
+    delta: wasm_memory_pages_t,
+) -> bool {
+    catch_panic(false, || (*m).memory.borrow_mut().grow(delta))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_memory_as_extern(m: *mut wasm_memory_t) -> *mut wasm_extern_t {
+    let ext = Extern::Memory((*m).memory.clone());
+    let ext = Box::new(wasm_extern_t {
+        ext: Rc::new(RefCell::new(ext)),
+    });
+    Box::into_raw(ext)
+}
+
+impl wasm_tabletype_t {
+    fn new(tabletype: TableType) -> wasm_tabletype_t {
+        let element_cache = wasm_valtype_t {
+            ty: tabletype.element().clone(),
+        };
+        let limits_cache = wasm_limits_t {
+            min: tabletype.limits().min(),
+            max: tabletype.limits().max(),
+        };
+        wasm_tabletype_t {
+            tabletype,
+            element_cache,
+            limits_cache,
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_tabletype_new(
+    ty: *mut wasm_valtype_t,
+    limits: *const wasm_limits_t,
+) -> *mut wasm_tabletype_t {
+    let ty = Box::from_raw(ty);
+    let limits = Limits::new((*limits).min, (*limits).max);
+    let tt = Box::new(wasm_tabletype_t::new(TableType::new(ty.ty, limits)));
+    Box::into_raw(tt)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_tabletype_delete(tt: *mut wasm_tabletype_t) {
+    let _ = Box::from_raw(tt);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_tabletype_element(
+    tt: *const wasm_tabletype_t,
+) -> *const wasm_valtype_t {
+    &(*tt).element_cache
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_tabletype_limits(
+    tt: *const wasm_tabletype_t,
+) -> *const wasm_limits_t {
+    &(*tt).limits_cache
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_table_new(
+    store: *mut wasm_store_t,
+    tt: *const wasm_tabletype_t,
+    init: *mut wasm_ref_t,
+) -> *mut wasm_table_t {
+    let store = (*store).store.clone();
+    let tabletype = (*tt).tabletype.clone();
+    let init = into_ref_val(init);
+    catch_panic(ptr::null_mut(), || {
+        match Table::new(store, tabletype, init) {
+            Ok(table) => Box::into_raw(Box::new(wasm_table_t {
+                table: Rc::new(RefCell::new(table)),
+            })),
+            Err(_) => ptr::null_mut(),
+        }
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_table_delete(t: *mut wasm_table_t) {
+    let _ = Box::from_raw(t);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_table_type(t: *const wasm_table_t) -> *mut wasm_tabletype_t {
+    let tabletype = (*t).table.borrow().r#type().clone();
+    let tt = Box::new(wasm_tabletype_t::new(tabletype));
+    Box::into_raw(tt)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_table_get(
+    t: *const wasm_table_t,
+    index: wasm_table_size_t,
+) -> *mut wasm_ref_t {
+    catch_panic(ptr::null_mut(), || {
+        from_ref_val((*t).table.borrow().get(index))
+    })
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_table_set(
+    t: *mut wasm_table_t,
+    index: wasm_table_size_t,
+    r: *mut wasm_ref_t,
+) -> bool {
+    let r = into_ref_val(r);
+    catch_panic(false, || (*t).table.borrow_mut().set(index, r))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_table_size(t: *const wasm_table_t) -> wasm_table_size_t {
+    (*t).table.borrow().size()
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_table_grow(
+    t: *mut wasm_table_t,
+    delta: wasm_table_size_t,
+    init: *mut wasm_ref_t,
+) -> bool {
+    let init = into_ref_val(init);
+    catch_panic(false, || (*t).table.borrow_mut().grow(delta, init))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_table_as_extern(t: *mut wasm_table_t) -> *mut wasm_extern_t {
+    let ext = Extern::Table((*t).table.clone());
+    let ext = Box::new(wasm_extern_t {
+        ext: Rc::new(RefCell::new(ext)),
+    });
+    Box::into_raw(ext)
+}
+
+impl wasm_externtype_t {
+    fn new(ty: ExternType) -> wasm_externtype_t {
+        let cache = match &ty {
+            ExternType::ExternFunc(ft) => {
+                wasm_externtype_t_type_cache::Func(wasm_functype_t::new(ft.clone()))
+            }
+            ExternType::ExternGlobal(gt) => {
+                wasm_externtype_t_type_cache::Global(wasm_globaltype_t::new(gt.clone()))
+            }
+            ExternType::ExternMemory(mt) => {
+                wasm_externtype_t_type_cache::Memory(wasm_memorytype_t::new(mt.clone()))
+            }
+            ExternType::ExternTable(tt) => {
+                wasm_externtype_t_type_cache::Table(wasm_tabletype_t::new(tt.clone()))
+            }
+        };
+        wasm_externtype_t { ty, cache }
+    }
+}
+
+fn from_externtype(ty: &ExternType) -> wasm_externkind_t {
+    match ty {
+        ExternType::ExternFunc(_) => 0,
+        ExternType::ExternGlobal(_) => 1,
+        ExternType::ExternTable(_) => 2,
+        ExternType::ExternMemory(_) => 3,
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_externtype_delete(et: *mut wasm_externtype_t) {
+    let _ = Box::from_raw(et);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_externtype_kind(et: *const wasm_externtype_t) -> wasm_externkind_t {
+    from_externtype(&(*et).ty)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_externtype_as_functype(
+    et: *mut wasm_externtype_t,
+) -> *mut wasm_functype_t {
+    match &mut (*et).cache {
+        wasm_externtype_t_type_cache::Func(ft) => ft,
+        _ => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_externtype_as_functype_const(
+    et: *const wasm_externtype_t,
+) -> *const wasm_functype_t {
+    wasm_externtype_as_functype(et as *mut wasm_externtype_t)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_externtype_as_globaltype(
+    et: *mut wasm_externtype_t,
+) -> *mut wasm_globaltype_t {
+    match &mut (*et).cache {
+        wasm_externtype_t_type_cache::Global(gt) => gt,
+        _ => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_externtype_as_globaltype_const(
+    et: *const wasm_externtype_t,
+) -> *const wasm_globaltype_t {
+    wasm_externtype_as_globaltype(et as *mut wasm_externtype_t)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_externtype_as_memorytype(
+    et: *mut wasm_externtype_t,
+) -> *mut wasm_memorytype_t {
+    match &mut (*et).cache {
+        wasm_externtype_t_type_cache::Memory(mt) => mt,
+        _ => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_externtype_as_memorytype_const(
+    et: *const wasm_externtype_t,
+) -> *const wasm_memorytype_t {
+    wasm_externtype_as_memorytype(et as *mut wasm_externtype_t)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_externtype_as_tabletype(
+    et: *mut wasm_externtype_t,
+) -> *mut wasm_tabletype_t {
+    match &mut (*et).cache {
+        wasm_externtype_t_type_cache::Table(tt) => tt,
+        _ => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_externtype_as_tabletype_const(
+    et: *const wasm_externtype_t,
+) -> *const wasm_tabletype_t {
+    wasm_externtype_as_tabletype(et as *mut wasm_externtype_t)
+}
+
+/// Copies `name` into a vector that lives as long as the type it is cached
+/// in.
+fn into_name(name: String) -> wasm_name_t {
+    let mut vec = wasm_name_t {
+        size: 0,
+        data: ptr::null_mut(),
+    };
+    vec.set_buffer(name.bytes().map(|b| b as wasm_byte_t).collect());
+    vec
+}
+
+impl wasm_importtype_t {
+    fn new(ty: ImportType) -> wasm_importtype_t {
+        let module_cache = into_name(ty.module().to_string());
+        let name_cache = into_name(ty.name().to_string());
+        let type_cache = wasm_externtype_t::new(ty.r#type().clone());
+        wasm_importtype_t {
+            ty,
+            module_cache,
+            name_cache,
+            type_cache,
+        }
+    }
+}
+
+impl Clone for wasm_importtype_t {
+    fn clone(&self) -> wasm_importtype_t {
+        wasm_importtype_t::new(self.ty.clone())
+    }
+}
+
+impl Drop for wasm_importtype_t {
+    fn drop(&mut self) {
+        unsafe {
+            wasm_byte_vec_delete(&mut self.module_cache);
+            wasm_byte_vec_delete(&mut self.name_cache);
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_importtype_delete(it: *mut wasm_importtype_t) {
+    let _ = Box::from_raw(it);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_importtype_module(
+    it: *const wasm_importtype_t,
+) -> *const wasm_name_t {
+    &(*it).module_cache
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_importtype_name(it: *const wasm_importtype_t) -> *const wasm_name_t {
+    &(*it).name_cache
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_importtype_type(
+    it: *const wasm_importtype_t,
+) -> *const wasm_externtype_t {
+    &(*it).type_cache
+}
+
+impl wasm_exporttype_t {
+    fn new(ty: ExportType) -> wasm_exporttype_t {
+        let name_cache = into_name(ty.name().to_string());
+        let type_cache = wasm_externtype_t::new(ty.r#type().clone());
+        wasm_exporttype_t {
+            ty,
+            name_cache,
+            type_cache,
+        }
+    }
+}
+
+impl Clone for wasm_exporttype_t {
+    fn clone(&self) -> wasm_exporttype_t {
+        wasm_exporttype_t::new(self.ty.clone())
+    }
+}
+
+impl Drop for wasm_exporttype_t {
+    fn drop(&mut self) {
+        unsafe { wasm_byte_vec_delete(&mut self.name_cache) };
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_exporttype_delete(et: *mut wasm_exporttype_t) {
+    let _ = Box::from_raw(et);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_exporttype_name(et: *const wasm_exporttype_t) -> *const wasm_name_t {
+    &(*et).name_cache
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_exporttype_type(
+    et: *const wasm_exporttype_t,
+) -> *const wasm_externtype_t {
+    &(*et).type_cache
+}
+
+/// Reads a possibly null reference argument; null is a null `anyref`.
+unsafe fn into_ref_val(r: *const wasm_ref_t) -> Val {
+    if r.is_null() {
+        Val::default()
+    } else {
+        (*r).r.clone()
+    }
+}
+
+/// Boxes a reference value into a new `wasm_ref_t`, or returns null for a
+/// null `anyref`.
+fn from_ref_val(r: Val) -> *mut wasm_ref_t {
+    match r {
+        Val::AnyRef(ref anyref) if anyref.borrow().is_null() => ptr::null_mut(),
+        Val::AnyRef(_) | Val::FuncRef(_) => Box::into_raw(Box::new(wasm_ref_t { r })),
+        _ => panic!("{:?} is not a reference", r),
+    }
+}
+
+/// The host info of a reference: a pointer owned by the embedder and the
+/// finalizer that is called when the referenced object is dropped.
+struct HostInfo {
+    info: *mut ::std::os::raw::c_void,
+    finalizer: ::std::option::Option<unsafe extern "C" fn(arg1: *mut ::std::os::raw::c_void)>,
+}
+
+impl Drop for HostInfo {
+    fn drop(&mut self) {
+        if let Some(finalizer) = self.finalizer {
+            unsafe {
+                finalizer(self.info);
+            }
+        }
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_ref_delete(r: *mut wasm_ref_t) {
+    if !r.is_null() {
+        let _ = Box::from_raw(r);
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_ref_copy(r: *const wasm_ref_t) -> *mut wasm_ref_t {
+    if r.is_null() {
+        return ptr::null_mut();
+    }
+    Box::into_raw(Box::new((*r).clone()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_ref_same(a: *const wasm_ref_t, b: *const wasm_ref_t) -> bool {
+    match (into_ref_val(a), into_ref_val(b)) {
+        (Val::AnyRef(a), Val::AnyRef(b)) => {
+            Rc::ptr_eq(&a, &b) || (a.borrow().is_null() && b.borrow().is_null())
+        }
+        (Val::FuncRef(a), Val::FuncRef(b)) => Rc::ptr_eq(&a, &b),
+        _ => false,
+    }
+}
+
+/// Only host objects created by `wasm_foreign_new` carry host info; it is
+/// null for functions.
+#[no_mangle]
+pub unsafe extern "C" fn wasm_ref_get_host_info(
+    r: *const wasm_ref_t,
+) -> *mut ::std::os::raw::c_void {
+    match into_ref_val(r) {
+        Val::AnyRef(anyref) => anyref
+            .borrow()
+            .host_info()
+            .and_then(|info| info.downcast_ref::<HostInfo>())
+            .map(|info| info.info)
+            .unwrap_or(ptr::null_mut()),
+        _ => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_ref_set_host_info(
+    r: *mut wasm_ref_t,
+    info: *mut ::std::os::raw::c_void,
+) {
+    wasm_ref_set_host_info_with_finalizer(r, info, None)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_ref_set_host_info_with_finalizer(
+    r: *mut wasm_ref_t,
+    info: *mut ::std::os::raw::c_void,
+    finalizer: ::std::option::Option<unsafe extern "C" fn(arg1: *mut ::std::os::raw::c_void)>,
+) {
+    match into_ref_val(r) {
+        Val::AnyRef(ref anyref) if !anyref.borrow().is_null() => {
+            let info: Box<dyn Any> = Box::new(HostInfo { info, finalizer });
+            anyref.borrow_mut().set_host_info(Some(info));
+        }
+        // Host info can only be set on foreign references.
+        _ => (),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_foreign_new(_store: *mut wasm_store_t) -> *mut wasm_foreign_t {
+    let foreign = Box::new(wasm_foreign_t {
+        anyref: Rc::new(RefCell::new(AnyRef::new(None))),
+    });
+    Box::into_raw(foreign)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_foreign_delete(foreign: *mut wasm_foreign_t) {
+    let _ = Box::from_raw(foreign);
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_foreign_copy(foreign: *const wasm_foreign_t) -> *mut wasm_foreign_t {
+    Box::into_raw(Box::new((*foreign).clone()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_foreign_same(
+    a: *const wasm_foreign_t,
+    b: *const wasm_foreign_t,
+) -> bool {
+    Rc::ptr_eq(&(*a).anyref, &(*b).anyref)
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_foreign_as_ref(foreign: *mut wasm_foreign_t) -> *mut wasm_ref_t {
+    from_ref_val(Val::AnyRef((*foreign).anyref.clone()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_ref_as_foreign(r: *mut wasm_ref_t) -> *mut wasm_foreign_t {
+    match into_ref_val(r) {
+        Val::AnyRef(ref anyref) if !anyref.borrow().is_null() => {
+            let foreign = Box::new(wasm_foreign_t {
+                anyref: anyref.clone(),
+            });
+            Box::into_raw(foreign)
+        }
+        _ => ptr::null_mut(),
+    }
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_func_as_ref(f: *mut wasm_func_t) -> *mut wasm_ref_t {
+    from_ref_val(Val::FuncRef((*f).func.clone()))
+}
+
+#[no_mangle]
+pub unsafe extern "C" fn wasm_ref_as_func(r: *mut wasm_ref_t) -> *mut wasm_func_t {
+    match into_ref_val(r) {
+        Val::FuncRef(func) => Box::into_raw(Box::new(wasm_func_t { func })),
+        _ => ptr::null_mut(),
+    }
 }
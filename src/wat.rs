@@ -0,0 +1,68 @@
+//! Support for the WebAssembly text format, enabled by the `wat` feature.
+
+use std::borrow::Cow;
+
+/// An error in a module given in the text format.
+#[derive(Fail, Debug)]
+#[fail(display = "{}:{}: {}", line, column, message)]
+pub struct WatError {
+    line: usize,
+    column: usize,
+    message: String,
+}
+
+impl WatError {
+    fn from_wabt(error: &wabt::Error) -> WatError {
+        let text = match error.kind() {
+            wabt::ErrorKind::Parse(text)
+            | wabt::ErrorKind::ResolveNames(text)
+            | wabt::ErrorKind::Validate(text) => text.clone(),
+            kind => format!("{:?}", kind),
+        };
+        // wabt reports "<file>:<line>:<column>: error: <message>".
+        let first_line = text.lines().next().unwrap_or("");
+        let mut parts = first_line.splitn(4, ':').skip(1);
+        let line = parts.next().and_then(|s| s.trim().parse().ok());
+        let column = parts.next().and_then(|s| s.trim().parse().ok());
+        match (line, column, parts.next()) {
+            (Some(line), Some(column), Some(message)) => WatError {
+                line,
+                column,
+                message: message
+                    .trim()
+                    .trim_start_matches("error:")
+                    .trim()
+                    .to_string(),
+            },
+            _ => WatError {
+                line: 0,
+                column: 0,
+                message: text,
+            },
+        }
+    }
+
+    /// The 1-based line of the error, or 0 if unknown.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// The 1-based column of the error, or 0 if unknown.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+/// Converts `bytes` to a binary module, unless they already are one.
+pub(crate) fn to_wasm(bytes: &[u8]) -> Result<Cow<[u8]>, WatError> {
+    if bytes.starts_with(b"\0asm") {
+        return Ok(Cow::Borrowed(bytes));
+    }
+    wabt::wat2wasm(bytes)
+        .map(Cow::Owned)
+        .map_err(|e| WatError::from_wabt(&e))
+}
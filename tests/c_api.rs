@@ -0,0 +1,352 @@
+//! Feeds malformed modules and bad arguments through the C API, which
+//! must report them with null results or traps instead of aborting.
+
+use std::ptr;
+use wasm_rust_api::wasm::*;
+
+// (module (import "" "f" (func)))
+const IMPORT_FUNC: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // type section
+    0x02, 0x06, 0x01, 0x00, 0x01, 0x66, 0x00, 0x00, // import section
+];
+
+// (module
+//   (import "" "f" (func $f (result i32)))
+//   (func (export "g") (result i32)
+//     call $f))
+const CALL_IMPORT: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x05, 0x01, 0x60, 0x00, 0x01, 0x7f, // type section
+    0x02, 0x06, 0x01, 0x00, 0x01, 0x66, 0x00, 0x00, // import section
+    0x03, 0x02, 0x01, 0x00, // function section
+    0x07, 0x05, 0x01, 0x01, 0x67, 0x00, 0x01, // export section
+    0x0a, 0x06, 0x01, 0x04, 0x00, 0x10, 0x00, 0x0b, // code section
+];
+
+// (module
+//   (func $start unreachable)
+//   (start $start))
+const START_TRAPS: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // type section
+    0x03, 0x02, 0x01, 0x00, // function section
+    0x08, 0x01, 0x00, // start section
+    0x0a, 0x05, 0x01, 0x03, 0x00, 0x00, 0x0b, // code section
+];
+
+fn byte_vec(bytes: &[u8]) -> wasm_byte_vec_t {
+    let mut vec = wasm_byte_vec_t {
+        size: 0,
+        data: ptr::null_mut(),
+    };
+    unsafe {
+        wasm_byte_vec_new(&mut vec, bytes.len(), bytes.as_ptr() as *const wasm_byte_t);
+    }
+    vec
+}
+
+unsafe fn trap_message(trap: *const wasm_trap_t) -> String {
+    let mut message = byte_vec(&[]);
+    wasm_trap_message(trap, &mut message);
+    let bytes = std::slice::from_raw_parts(message.data as *const u8, message.size);
+    let text = String::from_utf8_lossy(bytes)
+        .trim_end_matches('\0')
+        .to_string();
+    wasm_byte_vec_delete(&mut message);
+    text
+}
+
+unsafe fn new_module(store: *mut wasm_store_t, binary: &[u8]) -> *mut wasm_module_t {
+    let mut binary = byte_vec(binary);
+    let module = wasm_module_new(store, &binary);
+    wasm_byte_vec_delete(&mut binary);
+    assert!(!module.is_null(), "valid module rejected");
+    module
+}
+
+unsafe fn i32_global(store: *mut wasm_store_t) -> *mut wasm_global_t {
+    let gt = wasm_globaltype_new(wasm_valtype_new(0), 0);
+    let val = wasm_val_t {
+        kind: 0,
+        of: wasm_val_t__bindgen_ty_1 { i32: 1 },
+    };
+    let global = wasm_global_new(store, gt, &val);
+    wasm_globaltype_delete(gt);
+    assert!(!global.is_null());
+    global
+}
+
+#[test]
+fn rejects_malformed_modules() {
+    unsafe {
+        let engine = wasm_engine_new();
+        let store = wasm_store_new(engine);
+        for binary in &[&b""[..], &b"\0asm"[..], &b"\0asm\x01\0\0\0\x01\xff"[..]] {
+            let mut binary = byte_vec(binary);
+            assert!(!wasm_module_validate(store, &binary));
+            assert!(wasm_module_new(store, &binary).is_null());
+            assert!(wasm_module_deserialize(store, &binary).is_null());
+            wasm_byte_vec_delete(&mut binary);
+        }
+        wasm_store_delete(store);
+        wasm_engine_delete(engine);
+    }
+}
+
+#[test]
+fn rejects_unknown_kinds() {
+    unsafe {
+        let engine = wasm_engine_new();
+        let store = wasm_store_new(engine);
+        assert!(wasm_valtype_new(42).is_null());
+        // wasm.h has no kind for v128.
+        assert!(wasm_valtype_new(4).is_null());
+        assert!(wasm_globaltype_new(wasm_valtype_new(0), 42).is_null());
+
+        let gt = wasm_globaltype_new(wasm_valtype_new(0), 0);
+        let bad_val = wasm_val_t {
+            kind: 42,
+            of: wasm_val_t__bindgen_ty_1 { i64: 0 },
+        };
+        assert!(wasm_global_new(store, gt, &bad_val).is_null());
+        wasm_globaltype_delete(gt);
+        wasm_store_delete(store);
+        wasm_engine_delete(engine);
+    }
+}
+
+#[test]
+fn rejects_global_values_of_another_type() {
+    unsafe {
+        let engine = wasm_engine_new();
+        let store = wasm_store_new(engine);
+        let gt = wasm_globaltype_new(wasm_valtype_new(0), 1);
+        let val = wasm_val_t {
+            kind: 1,
+            of: wasm_val_t__bindgen_ty_1 { i64: 1 },
+        };
+        assert!(wasm_global_new(store, gt, &val).is_null());
+        wasm_globaltype_delete(gt);
+        wasm_store_delete(store);
+        wasm_engine_delete(engine);
+    }
+}
+
+#[test]
+fn takes_trap_messages_without_terminator() {
+    unsafe {
+        let engine = wasm_engine_new();
+        let store = wasm_store_new(engine);
+        let mut message = byte_vec(b"oops");
+        let trap = wasm_trap_new(store, &message);
+        assert_eq!(trap_message(trap), "oops");
+        wasm_trap_delete(trap);
+        wasm_byte_vec_delete(&mut message);
+        wasm_store_delete(store);
+        wasm_engine_delete(engine);
+    }
+}
+
+#[test]
+fn traps_on_mismatched_imports() {
+    unsafe {
+        let engine = wasm_engine_new();
+        let store = wasm_store_new(engine);
+        let module = new_module(store, IMPORT_FUNC);
+        let global = i32_global(store);
+        let imports = [wasm_global_as_extern(global) as *const wasm_extern_t];
+        let mut trap = ptr::null_mut();
+        let instance = wasm_instance_new(store, module, imports.as_ptr(), &mut trap);
+        assert!(instance.is_null());
+        assert!(!trap.is_null());
+        wasm_trap_delete(trap);
+
+        wasm_extern_delete(imports[0] as *mut wasm_extern_t);
+        wasm_global_delete(global);
+        wasm_module_delete(module);
+        wasm_store_delete(store);
+        wasm_engine_delete(engine);
+    }
+}
+
+#[test]
+fn converts_externs_of_another_kind_to_null() {
+    unsafe {
+        let engine = wasm_engine_new();
+        let store = wasm_store_new(engine);
+        let global = i32_global(store);
+        let ext = wasm_global_as_extern(global);
+        assert!(wasm_extern_as_func(ext).is_null());
+        assert!(wasm_extern_as_memory(ext).is_null());
+        assert!(wasm_extern_as_table(ext).is_null());
+        let same = wasm_extern_as_global(ext);
+        assert!(!same.is_null());
+
+        wasm_global_delete(same);
+        wasm_extern_delete(ext);
+        wasm_global_delete(global);
+        wasm_store_delete(store);
+        wasm_engine_delete(engine);
+    }
+}
+
+unsafe extern "C" fn returns_i64(
+    _args: *const wasm_val_t,
+    results: *mut wasm_val_t,
+) -> *mut wasm_trap_t {
+    *results = wasm_val_t {
+        kind: 1,
+        of: wasm_val_t__bindgen_ty_1 { i64: 1 },
+    };
+    ptr::null_mut()
+}
+
+#[test]
+fn traps_on_callback_results_of_another_type() {
+    unsafe {
+        let engine = wasm_engine_new();
+        let store = wasm_store_new(engine);
+        let module = new_module(store, CALL_IMPORT);
+
+        let mut params = wasm_valtype_vec_t {
+            size: 0,
+            data: ptr::null_mut(),
+        };
+        wasm_valtype_vec_new_empty(&mut params);
+        let mut results = wasm_valtype_vec_t {
+            size: 0,
+            data: ptr::null_mut(),
+        };
+        let result = wasm_valtype_new(0);
+        wasm_valtype_vec_new(&mut results, 1, &result);
+        let ft = wasm_functype_new(&mut params, &mut results);
+        let func = wasm_func_new(store, ft, Some(returns_i64));
+        assert!(!func.is_null());
+
+        let imports = [wasm_func_as_extern(func) as *const wasm_extern_t];
+        let mut trap = ptr::null_mut();
+        let instance = wasm_instance_new(store, module, imports.as_ptr(), &mut trap);
+        assert!(!instance.is_null());
+        let mut exports = wasm_extern_vec_t {
+            size: 0,
+            data: ptr::null_mut(),
+        };
+        wasm_instance_exports(instance, &mut exports);
+        let g = wasm_extern_as_func(*exports.data);
+        assert!(!g.is_null());
+
+        let mut out = wasm_val_t {
+            kind: 0,
+            of: wasm_val_t__bindgen_ty_1 { i32: 0 },
+        };
+        let trap = wasm_func_call(g, ptr::null(), &mut out);
+        assert!(!trap.is_null(), "mismatched result returned");
+        wasm_trap_delete(trap);
+
+        wasm_func_delete(g);
+        wasm_extern_vec_delete(&mut exports);
+        wasm_instance_delete(instance);
+        wasm_extern_delete(imports[0] as *mut wasm_extern_t);
+        wasm_func_delete(func);
+        wasm_functype_delete(ft);
+        wasm_module_delete(module);
+        wasm_store_delete(store);
+        wasm_engine_delete(engine);
+    }
+}
+
+#[test]
+fn accepts_null_funcref_globals() {
+    unsafe {
+        let engine = wasm_engine_new();
+        let store = wasm_store_new(engine);
+        let gt = wasm_globaltype_new(wasm_valtype_new(129), 1);
+        let val = wasm_val_t {
+            kind: 129,
+            of: wasm_val_t__bindgen_ty_1 {
+                ref_: ptr::null_mut(),
+            },
+        };
+        let global = wasm_global_new(store, gt, &val);
+        assert!(!global.is_null());
+        let mut out = wasm_val_t {
+            kind: 0,
+            of: wasm_val_t__bindgen_ty_1 { i32: 0 },
+        };
+        wasm_global_get(global, &mut out);
+        assert!(out.of.ref_.is_null());
+        wasm_val_delete(&mut out);
+
+        wasm_global_delete(global);
+        wasm_globaltype_delete(gt);
+        wasm_store_delete(store);
+        wasm_engine_delete(engine);
+    }
+}
+
+unsafe extern "C" fn does_nothing(
+    _args: *const wasm_val_t,
+    _results: *mut wasm_val_t,
+) -> *mut wasm_trap_t {
+    ptr::null_mut()
+}
+
+#[test]
+fn traps_on_arguments_of_another_type() {
+    unsafe {
+        let engine = wasm_engine_new();
+        let store = wasm_store_new(engine);
+        let mut params = wasm_valtype_vec_t {
+            size: 0,
+            data: ptr::null_mut(),
+        };
+        let param = wasm_valtype_new(0);
+        wasm_valtype_vec_new(&mut params, 1, &param);
+        let mut results = wasm_valtype_vec_t {
+            size: 0,
+            data: ptr::null_mut(),
+        };
+        wasm_valtype_vec_new_empty(&mut results);
+        let ft = wasm_functype_new(&mut params, &mut results);
+        let func = wasm_func_new(store, ft, Some(does_nothing));
+        assert!(!func.is_null());
+
+        let arg = wasm_val_t {
+            kind: 0,
+            of: wasm_val_t__bindgen_ty_1 { i32: 1 },
+        };
+        assert!(wasm_func_call(func, &arg, ptr::null_mut()).is_null());
+        let arg = wasm_val_t {
+            kind: 1,
+            of: wasm_val_t__bindgen_ty_1 { i64: 1 },
+        };
+        let trap = wasm_func_call(func, &arg, ptr::null_mut());
+        assert!(!trap.is_null(), "mismatched argument accepted");
+        wasm_trap_delete(trap);
+
+        wasm_func_delete(func);
+        wasm_functype_delete(ft);
+        wasm_store_delete(store);
+        wasm_engine_delete(engine);
+    }
+}
+
+#[test]
+fn returns_start_function_traps() {
+    unsafe {
+        let engine = wasm_engine_new();
+        let store = wasm_store_new(engine);
+        let module = new_module(store, START_TRAPS);
+        let mut trap = ptr::null_mut();
+        let instance = wasm_instance_new(store, module, ptr::null(), &mut trap);
+        assert!(instance.is_null());
+        assert!(!trap.is_null());
+        assert!(!trap_message(trap).is_empty());
+        wasm_trap_delete(trap);
+
+        wasm_module_delete(module);
+        wasm_store_delete(store);
+        wasm_engine_delete(engine);
+    }
+}
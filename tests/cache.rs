@@ -0,0 +1,52 @@
+use std::cell::RefCell;
+use std::fs;
+use std::rc::Rc;
+use std::sync::Arc;
+use wasm_rust_api::*;
+
+// (module (func (export "f")))
+const BINARY: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // type section
+    0x03, 0x02, 0x01, 0x00, // function section
+    0x07, 0x05, 0x01, 0x01, 0x66, 0x00, 0x00, // export section
+    0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b, // code section
+];
+
+#[cfg(unix)]
+fn cache_entries(directory: &std::path::Path) -> Vec<fs::DirEntry> {
+    fs::read_dir(directory)
+        .map(|entries| entries.map(|entry| entry.unwrap()).collect())
+        .unwrap_or_default()
+}
+
+#[cfg(unix)]
+#[test]
+fn second_compilation_hits_the_cache() {
+    use std::os::unix::fs::MetadataExt;
+
+    let directory =
+        std::env::temp_dir().join(format!("wasm-rust-api-cache-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    let mut config = Config::default();
+    config.set_cache_config(CacheConfig::new(directory.clone()));
+    let engine = Arc::new(Engine::new(config));
+    let store = Rc::new(RefCell::new(Store::new(engine).unwrap()));
+
+    Module::new(store.clone(), BINARY).unwrap();
+    let entries = cache_entries(&directory);
+    assert_eq!(entries.len(), 1);
+    let inode = entries[0].metadata().unwrap().ino();
+
+    // A miss would write the entry again, replacing the file.
+    let module = Module::new(store.clone(), BINARY).unwrap();
+    let entries = cache_entries(&directory);
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0].metadata().unwrap().ino(), inode);
+
+    let instance = Instance::new(store, Rc::new(RefCell::new(module)), &[]).unwrap();
+    let f = instance.exports()[0].borrow().func().clone();
+    assert!(f.borrow().call(&[]).is_ok());
+
+    fs::remove_dir_all(&directory).unwrap();
+}
@@ -0,0 +1,94 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+use wasm_rust_api::*;
+
+fn store() -> Rc<RefCell<Store>> {
+    let engine = Arc::new(Engine::new(Config::default()));
+    Rc::new(RefCell::new(Store::new(engine).unwrap()))
+}
+
+// (module
+//   (import "" "f" (func $f))
+//   (func (export "g")
+//     call $f))
+const CALL_IMPORT: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // type section
+    0x02, 0x06, 0x01, 0x00, 0x01, 0x66, 0x00, 0x00, // import section
+    0x03, 0x02, 0x01, 0x00, // function section
+    0x07, 0x05, 0x01, 0x01, 0x67, 0x00, 0x01, // export section
+    0x0a, 0x06, 0x01, 0x04, 0x00, 0x10, 0x00, 0x0b, // code section
+];
+
+struct Panics;
+
+impl Callable for Panics {
+    fn call(&self, _params: &[Val], _results: &mut [Val]) -> Result<(), Rc<RefCell<Trap>>> {
+        panic!("host function panicked");
+    }
+}
+
+#[test]
+fn host_panics_trap() {
+    let store = store();
+    let ty = FuncType::new(Box::new([]), Box::new([]));
+    let f = Func::new(store.clone(), ty, Rc::new(Panics));
+    let module = Module::new(store.clone(), CALL_IMPORT).unwrap();
+    let imports = vec![Rc::new(RefCell::new(Extern::Func(Rc::new(RefCell::new(
+        f,
+    )))))];
+    let instance = Instance::new(store, Rc::new(RefCell::new(module)), &imports).unwrap();
+    let g = instance.exports()[0].borrow().func().clone();
+    assert!(g.borrow().call(&[]).is_err());
+}
+
+struct SetsGlobal(Rc<RefCell<Global>>);
+
+impl Callable for SetsGlobal {
+    fn call(&self, _params: &[Val], _results: &mut [Val]) -> Result<(), Rc<RefCell<Trap>>> {
+        self.0.borrow_mut().set(Val::from(2i32)).unwrap();
+        Ok(())
+    }
+}
+
+#[test]
+fn host_functions_called_from_wasm_use_the_store() {
+    let store = store();
+    let ty = GlobalType::new(ValType::I32, Mutability::Var);
+    let global = Rc::new(RefCell::new(
+        Global::new(store.clone(), ty, Val::from(1i32)).unwrap(),
+    ));
+    let ty = FuncType::new(Box::new([]), Box::new([]));
+    let f = Func::new(store.clone(), ty, Rc::new(SetsGlobal(global.clone())));
+    let module = Module::new(store.clone(), CALL_IMPORT).unwrap();
+    let imports = vec![Rc::new(RefCell::new(Extern::Func(Rc::new(RefCell::new(
+        f,
+    )))))];
+    let instance = Instance::new(store, Rc::new(RefCell::new(module)), &imports).unwrap();
+    let g = instance.exports()[0].borrow().func().clone();
+    g.borrow().call(&[]).unwrap();
+    match global.borrow().get() {
+        Val::I32(2) => {}
+        val => panic!("unexpected value {:?}", val),
+    }
+}
+
+struct Noop;
+
+impl Callable for Noop {
+    fn call(&self, _params: &[Val], _results: &mut [Val]) -> Result<(), Rc<RefCell<Trap>>> {
+        Ok(())
+    }
+}
+
+#[test]
+fn traps_on_arguments_of_another_type() {
+    let ty = FuncType::new(Box::new([ValType::I32, ValType::FuncRef]), Box::new([]));
+    let f = Func::new(store(), ty, Rc::new(Noop));
+    assert!(f.call(&[Val::from(1i32), Val::default()]).is_ok());
+    assert!(f.call(&[Val::from(1i32)]).is_err());
+    assert!(f.call(&[Val::from(1i64), Val::default()]).is_err());
+    let r = Rc::new(RefCell::new(AnyRef::new(None)));
+    assert!(f.call(&[Val::from(1i32), Val::from(r)]).is_err());
+}
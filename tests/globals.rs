@@ -0,0 +1,67 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+use wasm_rust_api::*;
+
+fn store() -> Rc<RefCell<Store>> {
+    let engine = Arc::new(Engine::new(Config::default()));
+    Rc::new(RefCell::new(Store::new(engine).unwrap()))
+}
+
+#[test]
+fn rejects_initial_values_of_another_type() {
+    let ty = GlobalType::new(ValType::I32, Mutability::Var);
+    assert!(Global::new(store(), ty, Val::from(1i64)).is_err());
+}
+
+#[test]
+fn sets_mutable_globals() {
+    let ty = GlobalType::new(ValType::I32, Mutability::Var);
+    let mut global = Global::new(store(), ty, Val::from(1i32)).unwrap();
+    global.set(Val::from(2i32)).unwrap();
+    match global.get() {
+        Val::I32(2) => {}
+        val => panic!("unexpected value {:?}", val),
+    }
+    assert!(global.set(Val::from(3i64)).is_err());
+}
+
+#[test]
+fn rejects_setting_immutable_globals() {
+    let ty = GlobalType::new(ValType::I32, Mutability::Const);
+    let mut global = Global::new(store(), ty, Val::from(1i32)).unwrap();
+    assert!(global.set(Val::from(2i32)).is_err());
+    match global.get() {
+        Val::I32(1) => {}
+        val => panic!("unexpected value {:?}", val),
+    }
+}
+
+#[test]
+fn funcref_globals_can_be_null() {
+    let ty = GlobalType::new(ValType::FuncRef, Mutability::Var);
+    let mut global = Global::new(store(), ty, Val::default()).unwrap();
+    match global.get() {
+        Val::AnyRef(ref r) if r.borrow().is_null() => {}
+        val => panic!("unexpected value {:?}", val),
+    }
+    global.set(Val::default()).unwrap();
+    assert!(global.set(Val::from(1i32)).is_err());
+}
+
+#[test]
+fn holds_v128_values() {
+    let ty = GlobalType::new(ValType::V128, Mutability::Var);
+    let value = 0x0102_0304_0506_0708_090a_0b0c_0d0e_0f10u128;
+    let mut global = Global::new(store(), ty, Val::from(value)).unwrap();
+    match global.get() {
+        Val::V128(v) => assert_eq!(v, value),
+        val => panic!("unexpected value {:?}", val),
+    }
+    global.set(Val::from(!value)).unwrap();
+    match global.get() {
+        Val::V128(v) => assert_eq!(v, !value),
+        val => panic!("unexpected value {:?}", val),
+    }
+    assert!(global.set(Val::from(1i64)).is_err());
+}
@@ -0,0 +1,203 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+use wasm_rust_api::*;
+
+fn store() -> Rc<RefCell<Store>> {
+    let engine = Arc::new(Engine::new(Config::default()));
+    Rc::new(RefCell::new(Store::new(engine).unwrap()))
+}
+
+// An empty module with a `target_features` section that declares
+// "+atomics".
+const TARGET_FEATURES: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x00, 0x1a, 0x0f, 0x74, 0x61, 0x72, 0x67, 0x65, 0x74, 0x5f, 0x66, 0x65, 0x61, 0x74, 0x75, 0x72,
+    0x65, 0x73, // custom section "target_features"
+    0x01, 0x2b, 0x07, 0x61, 0x74, 0x6f, 0x6d, 0x69, 0x63, 0x73, // +atomics
+];
+
+// An empty module with a `target_features` section that claims five
+// entries and has none.
+const TRUNCATED_TARGET_FEATURES: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x00, 0x11, 0x0f, 0x74, 0x61, 0x72, 0x67, 0x65, 0x74, 0x5f, 0x66, 0x65, 0x61, 0x74, 0x75, 0x72,
+    0x65, 0x73, // custom section "target_features"
+    0x05,
+];
+
+#[test]
+fn reads_target_features() {
+    let module = Module::new(store(), TARGET_FEATURES).unwrap();
+    assert_eq!(module.features(), &["atomics".to_string()]);
+}
+
+#[test]
+fn ignores_malformed_target_features() {
+    let module = Module::new(store(), TRUNCATED_TARGET_FEATURES).unwrap();
+    assert!(module.features().is_empty());
+    assert_eq!(module.custom_section("target_features"), Some(&[0x05][..]));
+}
+
+// (module (func (export "f")))
+const ONE_FUNCTION: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // type section
+    0x03, 0x02, 0x01, 0x00, // function section
+    0x07, 0x05, 0x01, 0x01, 0x66, 0x00, 0x00, // export section
+    0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b, // code section
+];
+
+// An empty module of the same size as `ONE_FUNCTION`, padded with a custom
+// section.
+const NO_FUNCTIONS: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x00, 0x15, 0x01, 0x78, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // custom section "x"
+];
+
+/// Replaces the copy of `ONE_FUNCTION` embedded in a serialized module.
+fn replace_binary(serialized: &mut [u8], binary: &[u8]) {
+    let start = serialized
+        .windows(ONE_FUNCTION.len())
+        .position(|window| window == ONE_FUNCTION)
+        .expect("embedded binary");
+    serialized[start..start + binary.len()].copy_from_slice(binary);
+}
+
+#[test]
+fn round_trips_serialized_modules() {
+    let store = store();
+    let serialized = Module::new(store.clone(), ONE_FUNCTION)
+        .unwrap()
+        .serialize();
+    let module = unsafe { Module::deserialize(store.clone(), &serialized) }.unwrap();
+    let instance = Instance::new(store, Rc::new(RefCell::new(module)), &[]).unwrap();
+    let f = instance.exports()[0].borrow().func().clone();
+    assert!(f.borrow().call(&[]).is_ok());
+}
+
+#[test]
+fn rejects_serialized_modules_with_an_invalid_binary() {
+    let store = store();
+    let mut serialized = Module::new(store.clone(), ONE_FUNCTION)
+        .unwrap()
+        .serialize();
+    let mut invalid = ONE_FUNCTION.to_vec();
+    invalid[9] = 0xff;
+    replace_binary(&mut serialized, &invalid);
+    assert!(unsafe { Module::deserialize(store, &serialized) }.is_err());
+}
+
+#[test]
+fn rejects_serialized_code_for_another_module() {
+    assert_eq!(ONE_FUNCTION.len(), NO_FUNCTIONS.len());
+    let store = store();
+    let mut serialized = Module::new(store.clone(), ONE_FUNCTION)
+        .unwrap()
+        .serialize();
+    replace_binary(&mut serialized, NO_FUNCTIONS);
+    let module = unsafe { Module::deserialize(store.clone(), &serialized) }.unwrap();
+    assert!(Instance::new(store, Rc::new(RefCell::new(module)), &[]).is_err());
+}
+
+fn store_with(config: Config) -> Rc<RefCell<Store>> {
+    Rc::new(RefCell::new(
+        Store::new(Arc::new(Engine::new(config))).unwrap(),
+    ))
+}
+
+#[test]
+fn reports_where_validation_fails() {
+    let store = store();
+    let err = Module::validate(&store.borrow(), b"\0asn\x01\0\0\0").unwrap_err();
+    assert_eq!(err.offset(), 0);
+    assert_eq!(err.message(), "Bad magic number");
+    let err = Module::validate(&store.borrow(), b"\0asm\x02\0\0\0").unwrap_err();
+    assert_eq!(err.offset(), 4);
+    assert_eq!(err.message(), "Bad version number");
+    assert!(Module::validate(&store.borrow(), ONE_FUNCTION).is_ok());
+}
+
+// (module (func (param v128)))
+const V128_PARAM: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x05, 0x01, 0x60, 0x01, 0x7b, 0x00, // type section
+    0x03, 0x02, 0x01, 0x00, // function section
+    0x0a, 0x04, 0x01, 0x02, 0x00, 0x0b, // code section
+];
+
+#[test]
+fn validates_against_the_configured_features() {
+    let store = store();
+    assert!(Module::validate(&store.borrow(), V128_PARAM).is_err());
+    assert!(Module::new(store, V128_PARAM).is_err());
+
+    let mut config = Config::default();
+    config.set_wasm_simd(true);
+    let store = store_with(config);
+    assert!(Module::validate(&store.borrow(), V128_PARAM).is_ok());
+}
+
+// An empty module with the custom sections "a" [1, 2], "b" [3] and "a" [4].
+const CUSTOM_SECTIONS: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x00, 0x04, 0x01, 0x61, 0x01, 0x02, // custom section "a"
+    0x00, 0x03, 0x01, 0x62, 0x03, // custom section "b"
+    0x00, 0x03, 0x01, 0x61, 0x04, // custom section "a"
+];
+
+#[test]
+fn reads_custom_sections() {
+    let module = Module::new(store(), CUSTOM_SECTIONS).unwrap();
+    let sections = module
+        .custom_sections()
+        .iter()
+        .map(|section| (section.name(), section.data()))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        sections,
+        [("a", &[1, 2][..]), ("b", &[3][..]), ("a", &[4][..])]
+    );
+    assert_eq!(module.custom_section("a"), Some(&[1, 2][..]));
+    assert_eq!(module.custom_section("c"), None);
+}
+
+// (module $m
+//   (func $f (export "f") (param $x i32)
+//     unreachable))
+const NAMED: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x05, 0x01, 0x60, 0x01, 0x7f, 0x00, // type section
+    0x03, 0x02, 0x01, 0x00, // function section
+    0x07, 0x05, 0x01, 0x01, 0x66, 0x00, 0x00, // export section
+    0x0a, 0x05, 0x01, 0x03, 0x00, 0x00, 0x0b, // code section
+    0x00, 0x17, 0x04, 0x6e, 0x61, 0x6d, 0x65, // custom section "name"
+    0x00, 0x02, 0x01, 0x6d, // module name
+    0x01, 0x04, 0x01, 0x00, 0x01, 0x66, // function names
+    0x02, 0x06, 0x01, 0x00, 0x01, 0x00, 0x01, 0x78, // local names
+];
+
+#[test]
+fn reads_names() {
+    let module = Module::new(store(), NAMED).unwrap();
+    let names = module.names();
+    assert_eq!(names.module(), Some("m"));
+    assert_eq!(names.function(0), Some("f"));
+    assert_eq!(names.function(1), None);
+    assert_eq!(names.local(0, 0), Some("x"));
+    assert_eq!(names.local(0, 1), None);
+}
+
+#[test]
+fn labels_traps_with_names() {
+    let store = store();
+    let module = Module::new(store.clone(), NAMED).unwrap();
+    let instance = Instance::new(store, Rc::new(RefCell::new(module)), &[]).unwrap();
+    let f = instance.exports()[0].borrow().func().clone();
+    let trap = f.borrow().call(&[Val::from(1i32)]).err().expect("trap");
+    let trap = trap.borrow();
+    assert_eq!(trap.trace().len(), 1);
+    assert_eq!(trap.trace()[0].module_name(), Some("m"));
+    assert_eq!(trap.trace()[0].func_name(), Some("f"));
+}
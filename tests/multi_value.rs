@@ -0,0 +1,81 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+use wasm_rust_api::*;
+
+// (module
+//   (func (export "swap") (param i32 i64 f32 f64) (result f64 f32 i64 i32)
+//     local.get 3
+//     local.get 2
+//     local.get 1
+//     local.get 0))
+const SWAP: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x0c, 0x01, 0x60, 0x04, 0x7f, 0x7e, 0x7d, 0x7c, 0x04, 0x7c, 0x7d, 0x7e,
+    0x7f, // type section
+    0x03, 0x02, 0x01, 0x00, // function section
+    0x07, 0x08, 0x01, 0x04, 0x73, 0x77, 0x61, 0x70, 0x00, 0x00, // export section
+    0x0a, 0x0c, 0x01, 0x0a, 0x00, 0x20, 0x03, 0x20, 0x02, 0x20, 0x01, 0x20, 0x00,
+    0x0b, // code section
+];
+
+// (module
+//   (func (export "three") (result i32 i32 i32)
+//     i32.const 1
+//     i32.const 2
+//     i32.const 3))
+const THREE: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x07, 0x01, 0x60, 0x00, 0x03, 0x7f, 0x7f, 0x7f, // type section
+    0x03, 0x02, 0x01, 0x00, // function section
+    0x07, 0x09, 0x01, 0x05, 0x74, 0x68, 0x72, 0x65, 0x65, 0x00, 0x00, // export section
+    0x0a, 0x0a, 0x01, 0x08, 0x00, 0x41, 0x01, 0x41, 0x02, 0x41, 0x03, 0x0b, // code
+];
+
+// (module (import "" "f" (func (result i32 i32 i32))))
+const IMPORT_THREE: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x07, 0x01, 0x60, 0x00, 0x03, 0x7f, 0x7f, 0x7f, // type section
+    0x02, 0x06, 0x01, 0x00, 0x01, 0x66, 0x00, 0x00, // import section
+];
+
+fn store_with_multi_value() -> Rc<RefCell<Store>> {
+    let mut config = Config::default();
+    config.set_wasm_multi_value(true);
+    let engine = Arc::new(Engine::new(config));
+    Rc::new(RefCell::new(Store::new(engine).unwrap()))
+}
+
+#[test]
+fn returns_a_value_in_each_return_register() {
+    let store = store_with_multi_value();
+    let module = Module::new(store.clone(), SWAP).unwrap();
+    let instance = Instance::new(store, Rc::new(RefCell::new(module)), &[]).unwrap();
+    let swap = instance.exports()[0].borrow().func().clone();
+    let results = swap
+        .borrow()
+        .call(&[
+            Val::from(1i32),
+            Val::from(2i64),
+            Val::from(3.5f32),
+            Val::from(4.5f64),
+        ])
+        .unwrap();
+    match *results {
+        [Val::F64(a), Val::F32(b), Val::I64(2), Val::I32(1)]
+            if f64::from_bits(a) == 4.5 && f32::from_bits(b) == 3.5 => {}
+        _ => panic!("unexpected results {:?}", results),
+    }
+}
+
+#[test]
+fn rejects_more_results_than_return_registers() {
+    let store = store_with_multi_value();
+    assert!(Module::new(store, THREE).is_err());
+}
+
+#[test]
+fn rejects_imports_with_more_results_than_return_registers() {
+    let store = store_with_multi_value();
+    assert!(Module::new(store, IMPORT_THREE).is_err());
+}
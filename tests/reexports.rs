@@ -0,0 +1,72 @@
+//! Imports that an instance exports again must act on the instance that
+//! defines them.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+use wasm_rust_api::*;
+
+fn store() -> Rc<RefCell<Store>> {
+    let engine = Arc::new(Engine::new(Config::default()));
+    Rc::new(RefCell::new(Store::new(engine).unwrap()))
+}
+
+// (module
+//   (import "" "m" (memory 1 2))
+//   (export "m" (memory 0)))
+const REEXPORT_MEMORY: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x02, 0x08, 0x01, 0x00, 0x01, 0x6d, 0x02, 0x01, 0x01, 0x02, // import section
+    0x07, 0x05, 0x01, 0x01, 0x6d, 0x02, 0x00, // export section
+];
+
+// (module
+//   (import "" "t" (table 1 2 anyfunc))
+//   (export "t" (table 0)))
+const REEXPORT_TABLE: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x02, 0x09, 0x01, 0x00, 0x01, 0x74, 0x01, 0x70, 0x01, 0x01, 0x02, // import section
+    0x07, 0x05, 0x01, 0x01, 0x74, 0x01, 0x00, // export section
+];
+
+fn instantiate(store: &Rc<RefCell<Store>>, binary: &[u8], import: Extern) -> Instance {
+    let module = Module::new(store.clone(), binary).unwrap();
+    let imports = vec![Rc::new(RefCell::new(import))];
+    Instance::new(store.clone(), Rc::new(RefCell::new(module)), &imports).unwrap()
+}
+
+#[test]
+fn grows_reexported_memories() {
+    let store = store();
+    let memory = Rc::new(RefCell::new(
+        Memory::new(store.clone(), MemoryType::new(Limits::new(1, 2))).unwrap(),
+    ));
+    let instance = instantiate(&store, REEXPORT_MEMORY, Extern::Memory(memory.clone()));
+    let reexported = instance.exports()[0].borrow().memory().clone();
+
+    assert!(reexported.borrow_mut().grow(1));
+    assert_eq!(reexported.borrow().size(), 2);
+    assert_eq!(memory.borrow().size(), 2);
+    assert!(!reexported.borrow_mut().grow(1));
+}
+
+#[test]
+fn grows_reexported_tables() {
+    let store = store();
+    let ty = TableType::new(ValType::FuncRef, Limits::new(1, 2));
+    let table = Rc::new(RefCell::new(
+        Table::new(store.clone(), ty, Val::default()).unwrap(),
+    ));
+    let instance = instantiate(&store, REEXPORT_TABLE, Extern::Table(table.clone()));
+    let reexported = instance.exports()[0].borrow().table().clone();
+
+    assert!(reexported.borrow_mut().grow(1, Val::default()));
+    assert_eq!(reexported.borrow().size(), 2);
+    assert_eq!(table.borrow().size(), 2);
+    assert!(reexported.borrow_mut().set(1, Val::default()));
+    match reexported.borrow().get(1) {
+        Val::AnyRef(ref r) => assert!(r.borrow().is_null()),
+        val => panic!("unexpected value {:?}", val),
+    }
+    assert!(!reexported.borrow_mut().grow(1, Val::default()));
+}
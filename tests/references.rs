@@ -0,0 +1,165 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::Arc;
+use wasm_rust_api::*;
+
+fn store() -> Rc<RefCell<Store>> {
+    let engine = Arc::new(Engine::new(Config::default()));
+    Rc::new(RefCell::new(Store::new(engine).unwrap()))
+}
+
+/// Host info that records when it is dropped.
+struct Finalized(Rc<Cell<bool>>);
+
+impl Drop for Finalized {
+    fn drop(&mut self) {
+        self.0.set(true);
+    }
+}
+
+fn host_ref(finalized: &Rc<Cell<bool>>) -> Rc<RefCell<AnyRef>> {
+    let info: Box<dyn std::any::Any> = Box::new(Finalized(finalized.clone()));
+    Rc::new(RefCell::new(AnyRef::new(Some(info))))
+}
+
+#[test]
+fn globals_hold_host_references() {
+    let store = store();
+    let first = Rc::new(Cell::new(false));
+    let second = Rc::new(Cell::new(false));
+    let r = host_ref(&first);
+
+    let ty = GlobalType::new(ValType::AnyRef, Mutability::Var);
+    let mut global = Global::new(store.clone(), ty, Val::from(r.clone())).unwrap();
+    match global.get() {
+        Val::AnyRef(ref got) => assert!(Rc::ptr_eq(got, &r)),
+        val => panic!("unexpected value {:?}", val),
+    }
+    for _ in 0..3 {
+        global.set(Val::from(r.clone())).unwrap();
+    }
+    global.set(Val::from(host_ref(&second))).unwrap();
+    drop(r);
+
+    // The store keeps the references alive, and releases them once.
+    assert!(!first.get() && !second.get());
+    drop(global);
+    drop(store);
+    assert!(first.get() && second.get());
+}
+
+/// A host function that records when it is dropped.
+struct FinalizedFn(Finalized);
+
+impl Callable for FinalizedFn {
+    fn call(&self, _params: &[Val], _results: &mut [Val]) -> Result<(), Rc<RefCell<Trap>>> {
+        Ok(())
+    }
+}
+
+#[test]
+fn globals_hold_function_references() {
+    let store = store();
+    let finalized = Rc::new(Cell::new(false));
+    let ty = FuncType::new(Box::new([]), Box::new([]));
+    let callable = Rc::new(FinalizedFn(Finalized(finalized.clone())));
+    let f = Rc::new(RefCell::new(Func::new(store.clone(), ty, callable)));
+
+    let ty = GlobalType::new(ValType::FuncRef, Mutability::Var);
+    let global = Global::new(store.clone(), ty, Val::from(f.clone())).unwrap();
+    match global.get() {
+        Val::FuncRef(ref got) => assert!(Rc::ptr_eq(got, &f)),
+        val => panic!("unexpected value {:?}", val),
+    }
+
+    // The function holds the store, so the store must not hold it back.
+    drop(f);
+    assert!(finalized.get());
+    match global.get() {
+        Val::AnyRef(ref got) => assert!(got.borrow().is_null()),
+        val => panic!("unexpected value {:?}", val),
+    }
+}
+
+#[test]
+fn tables_hold_host_references() {
+    let store = store();
+    let finalized = Rc::new(Cell::new(false));
+    let r = host_ref(&finalized);
+
+    let ty = TableType::new(ValType::AnyRef, Limits::new(2, 4));
+    let mut table = Table::new(store.clone(), ty, Val::default()).unwrap();
+    assert!(table.set(1, Val::from(r.clone())));
+    assert!(table.grow(1, Val::from(r.clone())));
+    for index in 1..3 {
+        match table.get(index) {
+            Val::AnyRef(ref got) => assert!(Rc::ptr_eq(got, &r)),
+            val => panic!("unexpected value {:?}", val),
+        }
+    }
+    match table.get(0) {
+        Val::AnyRef(ref got) => assert!(got.borrow().is_null()),
+        val => panic!("unexpected value {:?}", val),
+    }
+
+    drop(r);
+    drop(table);
+    assert!(!finalized.get());
+    drop(store);
+    assert!(finalized.get());
+}
+
+#[test]
+fn tables_reject_elements_of_another_type() {
+    let store = store();
+    let r = Rc::new(RefCell::new(AnyRef::new(None)));
+
+    let ty = TableType::new(ValType::FuncRef, Limits::new(1, 1));
+    assert!(Table::new(store.clone(), ty.clone(), Val::from(r.clone())).is_err());
+    let mut table = Table::new(store, ty, Val::default()).unwrap();
+    assert!(!table.set(0, Val::from(r)));
+}
+
+// (module
+//   (import "" "f" (func $f (param anyref)))
+//   (func (export "g") (param anyref)
+//     local.get 0
+//     call $f))
+const PASS_ANYREF: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x05, 0x01, 0x60, 0x01, 0x6f, 0x00, // type section
+    0x02, 0x06, 0x01, 0x00, 0x01, 0x66, 0x00, 0x00, // import section
+    0x03, 0x02, 0x01, 0x00, // function section
+    0x07, 0x05, 0x01, 0x01, 0x67, 0x00, 0x01, // export section
+    0x0a, 0x07, 0x01, 0x05, 0x00, 0x20, 0x00, 0x10, 0x00, 0x0b, // code section
+];
+
+struct Noop;
+
+impl Callable for Noop {
+    fn call(&self, _params: &[Val], _results: &mut [Val]) -> Result<(), Rc<RefCell<Trap>>> {
+        Ok(())
+    }
+}
+
+#[test]
+fn traps_on_anyrefs_passed_for_funcrefs() {
+    let mut config = Config::default();
+    config.set_wasm_reference_types(true);
+    let engine = Arc::new(Engine::new(config));
+    let store = Rc::new(RefCell::new(Store::new(engine).unwrap()));
+
+    // Both reference types have the same signature, so the import links.
+    let ty = FuncType::new(Box::new([ValType::FuncRef]), Box::new([]));
+    let f = Func::new(store.clone(), ty, Rc::new(Noop));
+    let module = Module::new(store.clone(), PASS_ANYREF).unwrap();
+    let imports = vec![Rc::new(RefCell::new(Extern::Func(Rc::new(RefCell::new(
+        f,
+    )))))];
+    let instance = Instance::new(store, Rc::new(RefCell::new(module)), &imports).unwrap();
+    let g = instance.exports()[0].borrow().func().clone();
+
+    assert!(g.borrow().call(&[Val::default()]).is_ok());
+    let r = Rc::new(RefCell::new(AnyRef::new(None)));
+    assert!(g.borrow().call(&[Val::from(r)]).is_err());
+}
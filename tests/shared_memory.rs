@@ -0,0 +1,138 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::slice;
+use std::sync::Arc;
+use std::thread;
+use wasm_rust_api::*;
+
+// (module
+//   (import "" "m" (memory 1 1 shared))
+//   (func (export "store") (param i32 i32)
+//     local.get 0
+//     local.get 1
+//     i32.store))
+const BINARY: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x06, 0x01, 0x60, 0x02, 0x7f, 0x7f, 0x00, // type section
+    0x02, 0x08, 0x01, 0x00, 0x01, 0x6d, 0x02, 0x03, 0x01, 0x01, // import section
+    0x03, 0x02, 0x01, 0x00, // function section
+    0x07, 0x09, 0x01, 0x05, 0x73, 0x74, 0x6f, 0x72, 0x65, 0x00, 0x00, // export section
+    0x0a, 0x0b, 0x01, 0x09, 0x00, 0x20, 0x00, 0x20, 0x01, 0x36, 0x02, 0x00, 0x0b, // code
+];
+
+// (module
+//   (import "" "m" (memory 1 1 shared))
+//   (func (export "grow") (param i32) (result i32)
+//     local.get 0
+//     memory.grow)
+//   (func (export "store") (param i32 i32)
+//     local.get 0
+//     local.get 1
+//     i32.store))
+const GROW_AND_STORE: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x0b, 0x02, 0x60, 0x01, 0x7f, 0x01, 0x7f, 0x60, 0x02, 0x7f, 0x7f,
+    0x00, // type section
+    0x02, 0x08, 0x01, 0x00, 0x01, 0x6d, 0x02, 0x03, 0x01, 0x01, // import section
+    0x03, 0x03, 0x02, 0x00, 0x01, // function section
+    0x07, 0x10, 0x02, 0x04, 0x67, 0x72, 0x6f, 0x77, 0x00, 0x00, 0x05, 0x73, 0x74, 0x6f, 0x72, 0x65,
+    0x00, 0x01, // export section
+    0x0a, 0x12, 0x02, 0x06, 0x00, 0x20, 0x00, 0x40, 0x00, 0x0b, 0x09, 0x00, 0x20, 0x00, 0x20, 0x01,
+    0x36, 0x02, 0x00, 0x0b, // code section
+];
+
+// (module
+//   (memory 1 1 shared)
+//   (func (export "add") (param i32 i32) (result i32)
+//     local.get 0
+//     local.get 1
+//     i32.atomic.rmw.add))
+const ATOMIC_ADD: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x07, 0x01, 0x60, 0x02, 0x7f, 0x7f, 0x01, 0x7f, // type section
+    0x03, 0x02, 0x01, 0x00, // function section
+    0x05, 0x04, 0x01, 0x03, 0x01, 0x01, // memory section
+    0x07, 0x07, 0x01, 0x03, 0x61, 0x64, 0x64, 0x00, 0x00, // export section
+    0x0a, 0x0c, 0x01, 0x0a, 0x00, 0x20, 0x00, 0x20, 0x01, 0xfe, 0x1e, 0x02, 0x00,
+    0x0b, // code section
+];
+
+fn store_with_threads() -> Rc<RefCell<Store>> {
+    let mut config = Config::default();
+    config.set_wasm_threads(true);
+    let engine = Arc::new(Engine::new(config));
+    Rc::new(RefCell::new(Store::new(engine).unwrap()))
+}
+
+#[test]
+fn instances_on_other_threads_share_the_memory() {
+    let shared = SharedMemory::new(MemoryType::new_shared(Limits::new(1, 1))).unwrap();
+    let threads = (0..4)
+        .map(|i: i32| {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                let store = store_with_threads();
+                let memory = Memory::from_shared(store.clone(), &shared).unwrap();
+                let module = Module::new(store.clone(), BINARY).unwrap();
+                let imports = vec![Rc::new(RefCell::new(Extern::Memory(Rc::new(
+                    RefCell::new(memory),
+                ))))];
+                let instance =
+                    Instance::new(store, Rc::new(RefCell::new(module)), &imports).unwrap();
+                let f = instance.exports()[0].borrow().func().clone();
+                f.borrow()
+                    .call(&[Val::from(i * 4), Val::from(i + 1)])
+                    .unwrap();
+            })
+        })
+        .collect::<Vec<_>>();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    let data = unsafe { slice::from_raw_parts(shared.data(), shared.data_size()) };
+    for i in 0..4 {
+        let mut value = [0; 4];
+        value.copy_from_slice(&data[i * 4..i * 4 + 4]);
+        assert_eq!(i32::from_le_bytes(value), i as i32 + 1);
+    }
+}
+
+#[test]
+fn shared_memories_need_a_maximum() {
+    let ty = MemoryType::new_shared(Limits::at_least(1));
+    assert!(SharedMemory::new(ty.clone()).is_err());
+    assert!(Memory::new(store_with_threads(), ty).is_err());
+}
+
+#[test]
+fn growing_from_wasm_keeps_the_shared_pages() {
+    let shared = SharedMemory::new(MemoryType::new_shared(Limits::new(1, 1))).unwrap();
+    let store = store_with_threads();
+    let memory = Memory::from_shared(store.clone(), &shared).unwrap();
+    let module = Module::new(store.clone(), GROW_AND_STORE).unwrap();
+    let imports = vec![Rc::new(RefCell::new(Extern::Memory(Rc::new(
+        RefCell::new(memory),
+    ))))];
+    let instance = Instance::new(store, Rc::new(RefCell::new(module)), &imports).unwrap();
+    let grow = instance.exports()[0].borrow().func().clone();
+    let write = instance.exports()[1].borrow().func().clone();
+
+    for (delta, expected) in &[(0, 1), (1, -1)] {
+        match grow.borrow().call(&[Val::from(*delta)]).unwrap()[0] {
+            Val::I32(pages) => assert_eq!(pages, *expected),
+            ref val => panic!("unexpected value {:?}", val),
+        }
+    }
+    write.borrow().call(&[Val::from(0), Val::from(42)]).unwrap();
+    let data = unsafe { slice::from_raw_parts(shared.data(), 4) };
+    assert_eq!(data, &42i32.to_le_bytes());
+}
+
+#[test]
+fn validates_atomic_instructions_with_threads_enabled() {
+    assert!(Module::validate(&store_with_threads().borrow(), ATOMIC_ADD).is_ok());
+    let engine = Arc::new(Engine::new(Config::default()));
+    let store = Store::new(engine).unwrap();
+    assert!(Module::validate(&store, ATOMIC_ADD).is_err());
+}
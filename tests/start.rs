@@ -0,0 +1,81 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::Arc;
+use wasm_rust_api::*;
+
+fn store() -> Rc<RefCell<Store>> {
+    let engine = Arc::new(Engine::new(Config::default()));
+    Rc::new(RefCell::new(Store::new(engine).unwrap()))
+}
+
+// (module
+//   (import "" "f" (func $f))
+//   (start $f))
+const START_IMPORT: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // type section
+    0x02, 0x06, 0x01, 0x00, 0x01, 0x66, 0x00, 0x00, // import section
+    0x08, 0x01, 0x00, // start section
+];
+
+// (module
+//   (func $start unreachable)
+//   (start $start))
+const START_TRAPS: &[u8] = &[
+    0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00, // header
+    0x01, 0x04, 0x01, 0x60, 0x00, 0x00, // type section
+    0x03, 0x02, 0x01, 0x00, // function section
+    0x08, 0x01, 0x00, // start section
+    0x0a, 0x05, 0x01, 0x03, 0x00, 0x00, 0x0b, // code section
+];
+
+struct Called(Rc<Cell<bool>>);
+
+impl Callable for Called {
+    fn call(&self, _params: &[Val], _results: &mut [Val]) -> Result<(), Rc<RefCell<Trap>>> {
+        self.0.set(true);
+        Ok(())
+    }
+}
+
+#[test]
+fn runs_the_start_function() {
+    let store = store();
+    let module = Module::new(store.clone(), START_IMPORT).unwrap();
+    assert_eq!(module.start(), Some(0));
+    let called = Rc::new(Cell::new(false));
+    let ty = FuncType::new(Box::new([]), Box::new([]));
+    let f = Func::new(store.clone(), ty, Rc::new(Called(called.clone())));
+    let imports = vec![Rc::new(RefCell::new(Extern::Func(Rc::new(RefCell::new(
+        f,
+    )))))];
+    Instance::new(store, Rc::new(RefCell::new(module)), &imports).unwrap();
+    assert!(called.get());
+}
+
+#[test]
+fn returns_start_function_traps() {
+    let store = store();
+    let module = Module::new(store.clone(), START_TRAPS).unwrap();
+    let error = Instance::new(store, Rc::new(RefCell::new(module)), &[])
+        .err()
+        .expect("start function trapped");
+    let trap = error.downcast::<Trap>().expect("trap");
+    assert_eq!(trap.trace().len(), 1);
+    assert_eq!(trap.trace()[0].func_index(), 0);
+}
+
+#[test]
+fn link_errors_are_not_traps() {
+    let store = store();
+    let module = Module::new(store.clone(), START_IMPORT).unwrap();
+    let ty = GlobalType::new(ValType::I32, Mutability::Const);
+    let global = Global::new(store.clone(), ty, Val::from(1i32)).unwrap();
+    let imports = vec![Rc::new(RefCell::new(Extern::Global(Rc::new(
+        RefCell::new(global),
+    ))))];
+    let error = Instance::new(store, Rc::new(RefCell::new(module)), &imports)
+        .err()
+        .expect("link error");
+    assert!(error.downcast::<Trap>().is_err());
+}